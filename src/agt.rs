@@ -1,8 +1,17 @@
 use clap::Clap;
+use serde::{Deserialize, Serialize};
 use slidetown::parsers::agt;
 use std::{
     fs::File,
-    io::{Cursor, Read, Seek, SeekFrom},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{
+    error::{parse_file, parse_with, read_json, Error, PathContext, Result},
+    output::{print_json, relative_output_path, OutputFormat},
+    payload::read_payload,
+    Encode,
 };
 
 #[derive(Clap)]
@@ -17,6 +26,10 @@ pub struct AgtOpts {
 enum Command {
     #[clap(about = "display info about archive contents")]
     Info(InfoOpts),
+    #[clap(about = "unpack archive files and create manifest")]
+    Unpack(UnpackOpts),
+    #[clap(about = "pack archive files using manifest")]
+    Pack(PackOpts),
 }
 
 #[derive(Clap)]
@@ -25,7 +38,8 @@ struct InfoOpts {
     input_path: String,
 }
 
-static SPOOKY_KEY: &[u8] = &[
+/// Key the entry table and file data are XOR'd with.
+pub static SPOOKY_KEY: &[u8] = &[
     0x01, 0x05, 0x06, 0x02, 0x04, 0x03, 0x07, 0x08, 0x01, 0x05, 0x06, 0x0F, 0x04, 0x03, 0x07, 0x0C,
     0x31, 0x85, 0x76, 0x39, 0x34, 0x3D, 0x30, 0xE8, 0x67, 0x36, 0x36, 0x32, 0x3E, 0x33, 0x34, 0x3B,
     0x11, 0x15, 0x16, 0x16, 0x14, 0x13, 0x1D, 0x18, 0x11, 0x03, 0x06, 0x0C, 0x04, 0x03, 0x06, 0x08,
@@ -34,49 +48,360 @@ static SPOOKY_KEY: &[u8] = &[
     0x11, 0x15, 0x16, 0x10, 0x12, 0x13, 0x17, 0x38, 0xF1, 0x25,
 ];
 
-fn process_info(info_opts: InfoOpts) -> anyhow::Result<()> {
-    let mut file = File::open(&info_opts.input_path)?;
-    let header = agt::Header::parse(&mut file)?;
+/// The header is stored as is, everything after it is XOR'd with the key.
+const HEADER_LENGTH: u64 = 32;
+
+/// Decompressed size of the chunks `pack` writes. Compressed chunk lengths
+/// are u16, this leaves room for data that doesn't compress.
+pub const CHUNK_SIZE: usize = 0x8000;
 
-    let entry_count = header.file_count as usize;
-    let max_entry_path_length = 260;
-    let max_entry_size = 4 * 4 + max_entry_path_length;
+/// Write side of `agt::AgtReader`, XORs every byte at or past the header
+/// with the key.
+pub struct AgtWriter<'cipher, W: Write> {
+    inner: W,
+    /// Archive offset the next byte is written at
+    pos: u64,
+    cipher: &'cipher [u8],
+}
 
-    let entries_buffer_size = (entry_count * max_entry_size) as usize;
-    let mut entries_buffer = vec![0u8; entries_buffer_size];
+impl<'cipher, W: Write> AgtWriter<'cipher, W> {
+    pub fn new(inner: W, pos: u64, cipher: &'cipher [u8]) -> Self {
+        Self { inner, pos, cipher }
+    }
+}
 
-    file.seek(SeekFrom::Start(32))?;
-    file.read_exact(&mut entries_buffer)?;
+impl<'cipher, W: Write> Write for AgtWriter<'cipher, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let encrypted: Vec<u8> = buf
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| {
+                let pos = self.pos as usize + i;
+                if pos as u64 >= HEADER_LENGTH {
+                    byte ^ self.cipher[pos % self.cipher.len()]
+                } else {
+                    *byte
+                }
+            })
+            .collect();
 
-    #[allow(clippy::needless_range_loop)]
-    for i in 0..entries_buffer.len() {
-        let file_index = i + 32;
-        entries_buffer[i] ^= SPOOKY_KEY[file_index % SPOOKY_KEY.len()];
+        let bytes_written = self.inner.write(&encrypted)?;
+        self.pos += bytes_written as u64;
+        Ok(bytes_written)
     }
 
-    let mut entries_cursor = Cursor::new(entries_buffer);
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reads the entry table that follows the header.
+fn read_entries<R: Read + Seek>(
+    reader: &mut agt::AgtReader<R>,
+    header: &agt::Header,
+) -> Result<Vec<agt::Entry>> {
+    reader.seek(SeekFrom::Start(HEADER_LENGTH))?;
+    parse_with(reader, |reader| {
+        agt::Entry::parse_entries(reader, header.file_count as usize)
+    })
+}
+
+/// Reads and inflates the chunks of one file. A chunk is its u16 length
+/// followed by a zlib stream of that length, the lengths of all chunks come
+/// first.
+fn read_file<R: Read + Seek>(
+    reader: &mut agt::AgtReader<R>,
+    entry: &agt::Entry,
+) -> Result<Vec<u8>> {
+    let malformed = |offset: u32, error: anyhow::Error| Error::Parse {
+        path: None,
+        offset: Some(offset.into()),
+        error,
+    };
 
-    let entries = agt::Entry::parse_entries(&mut entries_cursor, entry_count)?;
+    let table_length = entry.chunk_count.checked_mul(2).ok_or_else(|| {
+        malformed(
+            entry.header_offset,
+            anyhow::anyhow!("{}: invalid chunk count {}", entry.path, entry.chunk_count),
+        )
+    })?;
+    let chunk_lengths: Vec<u16> = read_payload(
+        reader,
+        format_args!("{} chunk table", entry.path),
+        entry.header_offset,
+        table_length,
+    )?
+    .chunks_exact(2)
+    .map(|length| u16::from_le_bytes([length[0], length[1]]))
+    .collect();
+
+    let mut data = Vec::with_capacity(entry.decompressed_length as usize);
+    let mut offset = entry.header_offset.saturating_add(table_length);
+
+    for (chunk_index, &chunk_length) in chunk_lengths.iter().enumerate() {
+        let record = format_args!("{} chunk {}", entry.path, chunk_index);
+        if chunk_length < 2 {
+            return Err(malformed(
+                offset,
+                anyhow::anyhow!("{} is only {} bytes", record, chunk_length),
+            ));
+        }
+
+        let chunk = read_payload(reader, record, offset, chunk_length.into())?;
+        // the deflate data starts after the two byte zlib header, the
+        // checksum at the end is not verified
+        let inflated = miniz_oxide::inflate::decompress_to_vec(&chunk[2..]).map_err(|status| {
+            malformed(
+                offset,
+                anyhow::anyhow!("{} does not inflate: {:?}", record, status),
+            )
+        })?;
+        data.extend_from_slice(&inflated);
+
+        offset = offset.saturating_add(chunk_length.into());
+    }
+
+    if data.len() != entry.decompressed_length as usize {
+        return Err(Error::Inconsistent(vec![format!(
+            "{} inflated to {} bytes, the entry says {}",
+            entry.path,
+            data.len(),
+            entry.decompressed_length
+        )]));
+    }
+
+    Ok(data)
+}
 
-    println!("Version: {:?}", header.version);
+/// `agt info --format json` output.
+#[derive(Serialize)]
+struct Info {
+    /// Archive version as the parser names it
+    version: String,
+    files: Vec<InfoFile>,
+}
+
+#[derive(Serialize)]
+struct InfoFile {
+    /// Path inside the archive
+    path: String,
+    chunk_count: u32,
+    /// Size of the file once all chunks are decompressed
+    decompressed_length: u32,
+}
+
+fn process_info(info_opts: InfoOpts, format: OutputFormat) -> anyhow::Result<()> {
+    let input_path = Path::new(&info_opts.input_path);
+    let (header, file) = parse_file(input_path, agt::Header::parse)?;
+
+    let mut reader = agt::AgtReader::new(file, SPOOKY_KEY);
+    let entries = read_entries(&mut reader, &header).map_err(|e| e.in_file(input_path))?;
+
+    let info = Info {
+        version: format!("{:?}", header.version),
+        files: entries
+            .into_iter()
+            .map(|entry| InfoFile {
+                path: entry.path,
+                chunk_count: entry.chunk_count,
+                decompressed_length: entry.decompressed_length,
+            })
+            .collect(),
+    };
+
+    match format {
+        OutputFormat::Json => print_json(&info)?,
+        OutputFormat::Text => {
+            println!("Version: {}", info.version);
+
+            if info.files.is_empty() {
+                println!("No files");
+            } else {
+                println!("{} files:", info.files.len());
+                for file in info.files.iter() {
+                    println!(
+                        "- {} ({} chunk(s), {} bytes decompressed)",
+                        file.path, file.chunk_count, file.decompressed_length
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Unpacked archive manifest. The header fields are kept as they are, `files`
+/// lists the archive paths in entry order.
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub what: u32,
+    pub version: (u16, u16),
+    pub what2: u32,
+    pub what3: u32,
+    pub what4: u32,
+    pub files: Vec<String>,
+}
+
+#[derive(Clap)]
+struct UnpackOpts {
+    #[clap(short, long, about = "input file")]
+    input_path: String,
+    #[clap(short, long, about = "output directory")]
+    output_path: String,
+}
 
-    if header.file_count == 0 {
-        println!("No files");
-    } else {
-        println!("{} files:", header.file_count);
-        for archive_file in entries {
-            println!(
-                "- {} ({} chunk(s), {} bytes decompressed)",
-                archive_file.path, archive_file.chunk_count, archive_file.decompressed_length
-            );
+fn process_unpack(unpack_opts: UnpackOpts) -> anyhow::Result<()> {
+    unpack_file(
+        Path::new(&unpack_opts.input_path),
+        Path::new(&unpack_opts.output_path),
+    )?;
+
+    Ok(())
+}
+
+/// Parses the archive at `input_path` and unpacks it into `out_dir_path`.
+pub fn unpack_file(input_path: &Path, out_dir_path: &Path) -> Result<()> {
+    let (header, file) = parse_file(input_path, agt::Header::parse)?;
+
+    unpack(header, file, out_dir_path).map_err(|e| e.in_file(input_path))
+}
+
+/// Inflates every file in the archive into `out_dir_path` and writes
+/// `manifest.json` next to them.
+pub fn unpack<R: Read + Seek>(header: agt::Header, input: R, out_dir_path: &Path) -> Result<()> {
+    std::fs::create_dir_all(out_dir_path).with_path(out_dir_path)?;
+
+    let mut reader = agt::AgtReader::new(input, SPOOKY_KEY);
+    let entries = read_entries(&mut reader, &header)?;
+
+    for entry in entries.iter() {
+        let relative_path = relative_output_path(&entry.path);
+        if relative_path.as_os_str().is_empty() {
+            return Err(Error::Inconsistent(vec![format!(
+                "entry path {:?} has no file name",
+                entry.path
+            )]));
+        }
+
+        eprintln!("Writing {}", entry.path);
+
+        let data = read_file(&mut reader, entry)?;
+
+        let file_path = out_dir_path.join(relative_path);
+        let file_dir = file_path.with_file_name("");
+        std::fs::create_dir_all(&file_dir).with_path(&file_dir)?;
+        std::fs::write(&file_path, data).with_path(&file_path)?;
+    }
+
+    {
+        let manifest = Manifest {
+            what: header.what,
+            version: header.version,
+            what2: header.what2,
+            what3: header.what3,
+            what4: header.what4,
+            files: entries.into_iter().map(|entry| entry.path).collect(),
+        };
+        let manifest_path = out_dir_path.join("manifest.json");
+        let manifest_file = File::create(&manifest_path).with_path(&manifest_path)?;
+        serde_json::to_writer_pretty(manifest_file, &manifest)?;
+    }
+
+    Ok(())
+}
+
+#[derive(Clap)]
+struct PackOpts {
+    #[clap(short, long, about = "input manifest")]
+    input_path: String,
+    #[clap(short, long, about = "output file")]
+    output_path: String,
+}
+
+fn process_pack(pack_opts: PackOpts) -> anyhow::Result<()> {
+    pack_file(
+        Path::new(&pack_opts.input_path),
+        Path::new(&pack_opts.output_path),
+    )?;
+
+    Ok(())
+}
+
+/// Packs the manifest at `input_path` and the files next to it.
+pub fn pack_file(input_path: &Path, output_path: &Path) -> Result<()> {
+    let manifest: Manifest = read_json(input_path)?;
+
+    let out_file = File::create(output_path).with_path(output_path)?;
+    pack(&manifest, &input_path.with_file_name(""), out_file)
+}
+
+/// Writes an archive, files are read relative to `file_dir_path` and split
+/// into `CHUNK_SIZE` chunks. Compression is redone, so only archives written
+/// by `pack` come back byte for byte.
+pub fn pack<W: Write>(manifest: &Manifest, file_dir_path: &Path, mut out_file: W) -> Result<()> {
+    let mut entries = Vec::with_capacity(manifest.files.len());
+    let mut file_chunks = Vec::with_capacity(manifest.files.len());
+
+    for path in manifest.files.iter() {
+        let file_path = file_dir_path.join(relative_output_path(path));
+        let data = std::fs::read(&file_path).with_path(&file_path)?;
+
+        let chunks: Vec<Vec<u8>> = data
+            .chunks(CHUNK_SIZE)
+            .map(|chunk| miniz_oxide::deflate::compress_to_vec_zlib(chunk, 6))
+            .collect();
+
+        entries.push(agt::Entry {
+            header_offset: 0,
+            chunk_count: chunks.len() as u32,
+            decompressed_length: data.len() as u32,
+            path: path.clone(),
+        });
+        file_chunks.push(chunks);
+    }
+
+    // File data follows the entry table in order
+    let mut header_offset = HEADER_LENGTH + entries.encoded_len()?;
+    for (entry, chunks) in entries.iter_mut().zip(file_chunks.iter()) {
+        entry.header_offset = u32::try_from(header_offset).map_err(|_| {
+            Error::Inconsistent(vec!["archive would be larger than 4GB".to_string()])
+        })?;
+        header_offset += chunks
+            .iter()
+            .map(|chunk| 2 + chunk.len() as u64)
+            .sum::<u64>();
+    }
+
+    let header = agt::Header {
+        what: manifest.what,
+        version: manifest.version,
+        file_count: entries.len() as u32,
+        what2: manifest.what2,
+        what3: manifest.what3,
+        what4: manifest.what4,
+    };
+    header.encode(&mut out_file)?;
+
+    let mut writer = AgtWriter::new(out_file, HEADER_LENGTH, SPOOKY_KEY);
+    entries.encode(&mut writer)?;
+    for chunks in file_chunks.iter() {
+        for chunk in chunks.iter() {
+            // CHUNK_SIZE keeps even stored deflate blocks under u16::MAX
+            (chunk.len() as u16).encode(&mut writer)?;
+        }
+        for chunk in chunks.iter() {
+            writer.write_all(chunk)?;
         }
     }
 
     Ok(())
 }
 
-pub fn process_agt(agt_opts: AgtOpts) -> anyhow::Result<()> {
+pub fn process_agt(agt_opts: AgtOpts, format: OutputFormat) -> anyhow::Result<()> {
     match agt_opts.cmd {
-        Command::Info(info_opts) => process_info(info_opts),
+        Command::Info(info_opts) => process_info(info_opts, format),
+        Command::Unpack(unpack_opts) => process_unpack(unpack_opts),
+        Command::Pack(pack_opts) => process_pack(pack_opts),
     }
 }
@@ -0,0 +1,606 @@
+//! Little endian writers for the game formats, the counterpart to the
+//! `parse` functions in slidetown.
+//!
+//! Tables that point at nif payloads (LF blocks, LOF models, LBF block
+//! objects) are written with the offsets they hold, the pack functions fill
+//! those in before encoding. AGT entries are written in the clear, everything
+//! past the AGT header goes through `agt::AgtWriter` for the XOR.
+
+use std::io::{self, Write};
+
+use encoding_rs::EUC_KR;
+use slidetown::parsers::{agt, lbf, levelmodifier, lf, lof, loi};
+
+pub trait Encode {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()>;
+
+    /// Number of bytes `encode` writes.
+    fn encoded_len(&self) -> io::Result<u64> {
+        let mut counter = ByteCounter(0);
+        self.encode(&mut counter)?;
+        Ok(counter.0)
+    }
+}
+
+struct ByteCounter(u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Encode for u16 {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(&self.to_le_bytes())
+    }
+}
+
+impl Encode for u32 {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(&self.to_le_bytes())
+    }
+}
+
+impl Encode for i32 {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(&self.to_le_bytes())
+    }
+}
+
+impl Encode for f32 {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(&self.to_le_bytes())
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.0.encode(out)?;
+        self.1.encode(out)
+    }
+}
+
+impl<A: Encode, B: Encode, C: Encode> Encode for (A, B, C) {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.0.encode(out)?;
+        self.1.encode(out)?;
+        self.2.encode(out)
+    }
+}
+
+/// Elements back to back, counts are separate fields in every format.
+impl<T: Encode> Encode for [T] {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for item in self.iter() {
+            item.encode(out)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.as_slice().encode(out)
+    }
+}
+
+/// Null terminated EUC-KR string.
+fn encode_c_string<W: Write>(value: &str, out: &mut W) -> io::Result<()> {
+    let (encoded, _used_encoding, _had_errors) = EUC_KR.encode(value);
+    out.write_all(&encoded)?;
+    out.write_all(&[0u8; 1])
+}
+
+/// UTF-8 string behind its u32 byte length.
+fn encode_int_prefixed_string<W: Write>(value: &str, out: &mut W) -> io::Result<()> {
+    (value.len() as u32).encode(out)?;
+    out.write_all(value.as_bytes())
+}
+
+impl Encode for agt::Header {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(b"NayaPack")?;
+        self.what.encode(out)?;
+        self.version.encode(out)?;
+        self.file_count.encode(out)?;
+        self.what2.encode(out)?;
+        self.what3.encode(out)?;
+        self.what4.encode(out)
+    }
+}
+
+impl Encode for agt::Entry {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.header_offset.encode(out)?;
+        self.chunk_count.encode(out)?;
+        self.decompressed_length.encode(out)?;
+        encode_int_prefixed_string(&self.path, out)
+    }
+}
+
+impl Encode for lf::Header {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(b"LF\0\0kjc\0")?;
+        self.unknown1.encode(out)?;
+        self.version_date.encode(out)?;
+        self.unknown2.encode(out)?;
+        self.block_count.encode(out)?;
+        self.unknown3.encode(out)?;
+        self.size_x.encode(out)?;
+        self.size_y.encode(out)?;
+        self.size_idx.encode(out)?;
+        self.unknown4.encode(out)
+    }
+}
+
+impl Encode for lf::Block {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.index.encode(out)?;
+        self.position_x.encode(out)?;
+        self.position_y.encode(out)?;
+        self.file_offset.encode(out)?;
+        self.file_length.encode(out)?;
+        self.unknown.encode(out)
+    }
+}
+
+impl Encode for lf::Lf {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.header.encode(out)?;
+        self.blocks.encode(out)
+    }
+}
+
+impl Encode for lof::Header {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(b"LOF\0kjc\0")?;
+        self.unknown1.encode(out)?;
+        self.version_date.encode(out)?;
+        self.model_count.encode(out)?;
+        self.unknown2.encode(out)
+    }
+}
+
+impl Encode for lof::Model {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.index.encode(out)?;
+        self.unknown1.encode(out)?;
+        self.unknown2.encode(out)?;
+        self.unknown3.encode(out)?;
+        self.unknown4.encode(out)?;
+        self.unknown5.encode(out)?;
+        encode_c_string(&self.name, out)?;
+        encode_c_string(&self.file_name, out)?;
+        self.unknown6.encode(out)?;
+        self.unknown7.encode(out)?;
+        self.unknown8.encode(out)?;
+        self.file_offset.encode(out)?;
+        self.file_length.encode(out)
+    }
+}
+
+impl Encode for lof::Lof {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.header.encode(out)?;
+        self.models.encode(out)
+    }
+}
+
+impl Encode for lbf::Header {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(b"LBF\0kjc\0")?;
+        self.unknown1.encode(out)?;
+        self.version_date.encode(out)?;
+        self.unknown2.encode(out)?;
+        self.block_count.encode(out)?;
+        self.block_object_count.encode(out)
+    }
+}
+
+impl Encode for lbf::BlockObject {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.unk.encode(out)?;
+        self.index.encode(out)?;
+        self.file_offset.encode(out)?;
+        self.file_length.encode(out)
+    }
+}
+
+impl Encode for lbf::Block {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.object_count.encode(out)?;
+        self.objects.encode(out)
+    }
+}
+
+impl Encode for lbf::Lbf {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.header.encode(out)?;
+        self.blocks.encode(out)
+    }
+}
+
+impl Encode for loi::Header {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(b"LOI\0kjc\0")?;
+        self.unknown1.encode(out)?;
+        self.version_date.encode(out)?;
+        self.block_count.encode(out)
+    }
+}
+
+impl Encode for loi::BlockObject {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.unknown1.encode(out)?;
+        self.unknown2.encode(out)?;
+        self.unknown3.encode(out)?;
+        self.unknown4.encode(out)?;
+        self.object_index.encode(out)?;
+        self.block_index.encode(out)?;
+        self.model_table_index.encode(out)?;
+        self.position.encode(out)?;
+        self.rotation.encode(out)?;
+        self.scale.encode(out)?;
+        self.unknown8.encode(out)?;
+        self.unknown9.encode(out)?;
+        self.object_extra_index.encode(out)?;
+        self.unknown11.encode(out)
+    }
+}
+
+impl Encode for loi::Block {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.block_index.encode(out)?;
+        self.object_count.encode(out)?;
+        self.objects.encode(out)
+    }
+}
+
+impl Encode for loi::ObjectExtra {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.object_index.encode(out)?;
+        self.object_extra_index.encode(out)?;
+        self.unknown3.encode(out)?;
+        self.position.encode(out)?;
+        self.rotation.encode(out)?;
+        self.unknown4.encode(out)?;
+        self.unknown5.encode(out)
+    }
+}
+
+impl Encode for loi::UnknownObject2 {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.unknown_count.encode(out)?;
+        self.items.encode(out)
+    }
+}
+
+impl Encode for loi::UnknownObject3 {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.unknown1.encode(out)?;
+        self.unknown_count.encode(out)?;
+        self.items.encode(out)
+    }
+}
+
+impl Encode for loi::UnknownObject4 {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.unknown_count.encode(out)?;
+        self.unknown1.encode(out)?;
+        self.items.encode(out)
+    }
+}
+
+impl Encode for loi::UnknownObject5 {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.object_count.encode(out)?;
+        self.object_indices.encode(out)
+    }
+}
+
+impl Encode for loi::Loi {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.header.encode(out)?;
+        self.blocks.encode(out)?;
+        self.object_extra_count.encode(out)?;
+        self.object_extras.encode(out)?;
+        self.unknown_objects_2.encode(out)?;
+        self.unknown_object_3_count.encode(out)?;
+        self.unknown_objects_3.encode(out)?;
+        self.unknown_objects_4.encode(out)?;
+        self.unknown_objects_5.encode(out)
+    }
+}
+
+impl Encode for levelmodifier::Header {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(b"DPDB")?;
+        self.version_date.encode(out)?;
+        self.speed_length.encode(out)?;
+        self.accel_length.encode(out)?;
+        self.dura_length.encode(out)?;
+        self.boost_length.encode(out)?;
+        self.speed_ids.encode(out)?;
+        self.accel_ids.encode(out)?;
+        self.dura_ids.encode(out)?;
+        self.boost_ids.encode(out)
+    }
+}
+
+impl Encode for levelmodifier::GroupOption {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.values.encode(out)
+    }
+}
+
+impl Encode for levelmodifier::LevelModifier {
+    fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.header.encode(out)?;
+        self.speed.encode(out)?;
+        self.accel.encode(out)?;
+        self.dura.encode(out)?;
+        self.boost.encode(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use std::fmt::Debug;
+
+    use super::*;
+
+    /// `parse(write(x)) == x`, compared field by field so the offsets and
+    /// version dates serde skips are covered too.
+    fn assert_round_trip<T, F>(value: &T, parse: F)
+    where
+        T: Encode + Debug + PartialEq,
+        F: FnOnce(&mut Cursor<Vec<u8>>) -> anyhow::Result<T>,
+    {
+        let mut buffer = Vec::new();
+        value.encode(&mut buffer).unwrap();
+        assert_eq!(buffer.len() as u64, value.encoded_len().unwrap());
+
+        let parsed = parse(&mut Cursor::new(buffer)).unwrap();
+        assert_eq!(&parsed, value);
+    }
+
+    type Vector3 = (f32, f32, f32);
+
+    const IDENTITY: (Vector3, Vector3, Vector3) =
+        ((1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0));
+
+    #[test]
+    fn lf_round_trip() {
+        let lf = lf::Lf {
+            header: lf::Header {
+                unknown1: 1,
+                version_date: 20090406,
+                unknown2: 0,
+                block_count: 2,
+                unknown3: vec![0; 13],
+                size_x: 2,
+                size_y: 1,
+                size_idx: 1,
+                unknown4: vec![0.0; 5],
+            },
+            blocks: (0..2)
+                .map(|index| lf::Block {
+                    index,
+                    position_x: index,
+                    position_y: 0,
+                    file_offset: 0x100 + index * 0x20,
+                    file_length: 0x20,
+                    unknown: 0,
+                })
+                .collect(),
+        };
+
+        assert_round_trip(&lf, lf::Lf::parse);
+    }
+
+    #[test]
+    fn lof_round_trip() {
+        let lof = lof::Lof {
+            header: lof::Header {
+                unknown1: 1,
+                version_date: 20090406,
+                model_count: 2,
+                unknown2: 0,
+            },
+            models: ["tree", "나무"]
+                .iter()
+                .enumerate()
+                .map(|(index, name)| lof::Model {
+                    index: index as u32,
+                    unknown1: 0,
+                    unknown2: 0,
+                    unknown3: 0,
+                    unknown4: 0,
+                    unknown5: 0,
+                    name: name.to_string(),
+                    file_name: format!("model\\{}.nif", index),
+                    unknown6: 1.0,
+                    unknown7: 0,
+                    unknown8: 0,
+                    file_offset: 0x100 + index as u32 * 0x20,
+                    file_length: 0x20,
+                })
+                .collect(),
+        };
+
+        assert_round_trip(&lof, lof::Lof::parse);
+    }
+
+    #[test]
+    fn lbf_round_trip() {
+        let lbf = lbf::Lbf {
+            header: lbf::Header {
+                unknown1: 1,
+                version_date: 20090406,
+                unknown2: 0,
+                block_count: 1,
+                block_object_count: 2,
+            },
+            blocks: vec![lbf::Block {
+                object_count: 2,
+                objects: (0..2)
+                    .map(|index| lbf::BlockObject {
+                        unk: 0,
+                        index,
+                        file_offset: 0x100 + index * 0x20,
+                        file_length: 0x20,
+                    })
+                    .collect(),
+            }],
+        };
+
+        assert_round_trip(&lbf, lbf::Lbf::parse);
+    }
+
+    #[test]
+    fn loi_round_trip() {
+        let loi = loi::Loi {
+            header: loi::Header {
+                unknown1: 1,
+                version_date: 20090406,
+                block_count: 1,
+            },
+            blocks: vec![loi::Block {
+                block_index: 0,
+                object_count: 1,
+                objects: vec![loi::BlockObject {
+                    unknown1: 0,
+                    unknown2: 0,
+                    unknown3: 0.0,
+                    unknown4: 0.0,
+                    object_index: 0,
+                    block_index: 0,
+                    model_table_index: 1,
+                    position: (1.0, 2.0, 3.0),
+                    rotation: IDENTITY,
+                    scale: 1.0,
+                    unknown8: 0,
+                    unknown9: 0,
+                    object_extra_index: 0,
+                    unknown11: 0,
+                }],
+            }],
+            object_extra_count: 1,
+            object_extras: vec![loi::ObjectExtra {
+                object_index: 0,
+                object_extra_index: 0,
+                unknown3: 0,
+                position: (1.0, 2.0, 3.0),
+                rotation: IDENTITY,
+                unknown4: (0.0, 0.0, 0.0),
+                unknown5: 0.0,
+            }],
+            unknown_objects_2: vec![loi::UnknownObject2 {
+                unknown_count: 1,
+                items: vec![0],
+            }],
+            unknown_object_3_count: 1,
+            unknown_objects_3: vec![loi::UnknownObject3 {
+                unknown1: 0,
+                unknown_count: 1,
+                items: vec![0],
+            }],
+            unknown_objects_4: vec![loi::UnknownObject4 {
+                unknown_count: 1,
+                unknown1: 0,
+                items: vec![0],
+            }],
+            unknown_objects_5: vec![loi::UnknownObject5 {
+                object_count: 1,
+                object_indices: vec![0],
+            }],
+        };
+
+        assert_round_trip(&loi, loi::Loi::parse);
+    }
+
+    #[test]
+    fn agt_round_trip() {
+        let data = b"<config>the quick brown fox</config>".repeat(4);
+        let chunk = miniz_oxide::deflate::compress_to_vec_zlib(&data, 6);
+
+        let mut entry = agt::Entry {
+            header_offset: 0,
+            chunk_count: 1,
+            decompressed_length: data.len() as u32,
+            path: "data\\config.xml".to_string(),
+        };
+        entry.header_offset = 32 + entry.encoded_len().unwrap() as u32;
+        let header = agt::Header {
+            what: 1,
+            version: (1, 2),
+            file_count: 1,
+            what2: 0,
+            what3: 0,
+            what4: 0,
+        };
+
+        assert_round_trip(&header, agt::Header::parse);
+        assert_round_trip(&entry, agt::Entry::parse);
+
+        // everything past the header goes through the cipher, the reader
+        // has to undo it and inflate the chunk
+        let cipher = [0x5a, 0xa5, 0x3c];
+        let mut buffer = Vec::new();
+        header.encode(&mut buffer).unwrap();
+        {
+            let mut writer = crate::agt::AgtWriter::new(&mut buffer, 32, &cipher);
+            entry.encode(&mut writer).unwrap();
+            (chunk.len() as u16).encode(&mut writer).unwrap();
+            writer.write_all(&chunk).unwrap();
+        }
+
+        let mut cursor = Cursor::new(buffer);
+        assert_eq!(agt::Header::parse(&mut cursor).unwrap(), header);
+        let mut reader = agt::AgtReader::new(cursor, &cipher);
+        let entries = agt::Entry::parse_entries(&mut reader, 1).unwrap();
+        assert_eq!(entries, vec![entry]);
+        assert_eq!(reader.read_entry(&entries[0]).unwrap(), data);
+    }
+
+    #[test]
+    fn levelmodifier_round_trip() {
+        // one row per level, one value per id
+        let levels = |base: f32, id_count: usize| -> Vec<levelmodifier::GroupOption> {
+            (0..1001)
+                .map(|level| levelmodifier::GroupOption {
+                    values: (0..id_count)
+                        .map(|column| base + level as f32 * 0.01 + column as f32)
+                        .collect(),
+                })
+                .collect()
+        };
+        let levelmodifier = levelmodifier::LevelModifier {
+            header: levelmodifier::Header {
+                version_date: 20090406,
+                speed_length: 2,
+                accel_length: 1,
+                dura_length: 1,
+                boost_length: 1,
+                speed_ids: vec![1201, 1202],
+                accel_ids: vec![1301],
+                dura_ids: vec![1401],
+                boost_ids: vec![1501],
+            },
+            speed: levels(1.0, 2),
+            accel: levels(3.0, 1),
+            dura: levels(4.0, 1),
+            boost: levels(5.0, 1),
+        };
+
+        assert_round_trip(&levelmodifier, levelmodifier::LevelModifier::parse);
+    }
+}
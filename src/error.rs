@@ -0,0 +1,192 @@
+use std::{
+    fs::File,
+    io::Seek,
+    path::{Path, PathBuf},
+};
+
+/// Exit code for errors without a more specific class.
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit code when a file can't be opened, created, read or written.
+pub const EXIT_IO: i32 = 3;
+/// Exit code for input that doesn't parse.
+pub const EXIT_MALFORMED: i32 = 4;
+/// Exit code for input that parses but contradicts itself.
+pub const EXIT_INCONSISTENT: i32 = 5;
+
+/// Errors returned by the library functions. The command line front ends
+/// wrap these in `anyhow` for display.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{}: {source}", .path.display())]
+    File {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("{}: {source}", .path.display())]
+    Manifest {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// A slidetown parser rejected the input, `offset` is where it stopped.
+    #[error("{}failed to parse{}: {error:#}", location(.path), at_offset(.offset))]
+    Parse {
+        path: Option<PathBuf>,
+        offset: Option<u64>,
+        error: anyhow::Error,
+    },
+    /// An embedded nif could not be read, usually because the table points
+    /// past the end of the file.
+    #[error(
+        "{}{record} at offset {offset:#x} ({length} bytes): {source}",
+        location(.path)
+    )]
+    Payload {
+        path: Option<PathBuf>,
+        record: String,
+        offset: u32,
+        length: u32,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("inconsistent data: {}", .0.join("; "))]
+    Inconsistent(Vec<String>),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn location(path: &Option<PathBuf>) -> String {
+    match path {
+        Some(path) => format!("{}: ", path.display()),
+        None => String::new(),
+    }
+}
+
+fn at_offset(offset: &Option<u64>) -> String {
+    match offset {
+        Some(offset) => format!(" at offset {:#x}", offset),
+        None => String::new(),
+    }
+}
+
+impl Error {
+    /// Attaches the path of the file being read to errors that don't have
+    /// one yet.
+    pub fn in_file(self, file_path: &Path) -> Self {
+        match self {
+            Error::Io(source) => Error::File {
+                path: file_path.to_path_buf(),
+                source,
+            },
+            Error::Json(source) => Error::Manifest {
+                path: file_path.to_path_buf(),
+                source,
+            },
+            Error::Parse {
+                path: None,
+                offset,
+                error,
+            } => Error::Parse {
+                path: Some(file_path.to_path_buf()),
+                offset,
+                error,
+            },
+            Error::Payload {
+                path: None,
+                record,
+                offset,
+                length,
+                source,
+            } => Error::Payload {
+                path: Some(file_path.to_path_buf()),
+                record,
+                offset,
+                length,
+                source,
+            },
+            other => other,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::File { .. } | Error::Io(_) => EXIT_IO,
+            Error::Manifest { .. }
+            | Error::Json(_)
+            | Error::Parse { .. }
+            | Error::Payload { .. } => EXIT_MALFORMED,
+            Error::Inconsistent(_) => EXIT_INCONSISTENT,
+        }
+    }
+}
+
+/// Exit code for an error coming out of a command, found by walking its
+/// causes for something more specific than `EXIT_FAILURE`.
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    error
+        .chain()
+        .find_map(|cause| {
+            if let Some(error) = cause.downcast_ref::<Error>() {
+                Some(error.exit_code())
+            } else if cause.is::<std::io::Error>() {
+                Some(EXIT_IO)
+            } else if cause.is::<serde_json::Error>() {
+                Some(EXIT_MALFORMED)
+            } else {
+                None
+            }
+        })
+        .unwrap_or(EXIT_FAILURE)
+}
+
+/// Attaches a path to io errors from opening or creating files.
+pub trait PathContext<T> {
+    fn with_path(self, path: &Path) -> Result<T>;
+}
+
+impl<T> PathContext<T> for std::io::Result<T> {
+    fn with_path(self, path: &Path) -> Result<T> {
+        self.map_err(|source| Error::File {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+/// Runs one of the slidetown parsers, recording how far it got on failure.
+pub fn parse_with<R, T, F>(input: &mut R, parse: F) -> Result<T>
+where
+    R: Seek,
+    F: FnOnce(&mut R) -> anyhow::Result<T>,
+{
+    parse(input).map_err(|error| Error::Parse {
+        path: None,
+        offset: input.stream_position().ok(),
+        error,
+    })
+}
+
+/// Reads a JSON manifest.
+pub fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T> {
+    let file = File::open(path).with_path(path)?;
+    serde_json::from_reader(std::io::BufReader::new(file)).map_err(|source| Error::Manifest {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Opens `path` and parses it, handing back the file for reading the
+/// payloads the parsed tables point at.
+pub fn parse_file<T, F>(path: &Path, parse: F) -> Result<(T, File)>
+where
+    F: FnOnce(&mut File) -> anyhow::Result<T>,
+{
+    let mut file = File::open(path).with_path(path)?;
+    let parsed = parse_with(&mut file, parse).map_err(|e| e.in_file(path))?;
+    Ok((parsed, file))
+}
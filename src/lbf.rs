@@ -1,11 +1,20 @@
 use std::{
     fs::File,
-    io::{Cursor, Read, Seek, SeekFrom},
+    io::{Cursor, Read, Seek, Write},
+    path::{Path, PathBuf},
 };
 
 use clap::Clap;
+use serde::Serialize;
 use slidetown::parsers::lbf;
 
+use crate::{
+    error::{parse_file, read_json, PathContext, Result},
+    output::{print_json, OutputFormat},
+    payload::read_payload,
+    Encode,
+};
+
 #[derive(Clap)]
 pub struct LbfOpts {
     #[clap(subcommand, about = "subcommand to run")]
@@ -17,6 +26,12 @@ enum Command {
     #[clap(about = "display info about archive contents")]
     Info(InfoOpts),
 
+    #[clap(about = "unpack block object nifs and create manifest")]
+    Unpack(UnpackOpts),
+
+    #[clap(about = "pack block object nifs using manifest")]
+    Pack(PackOpts),
+
     #[clap(about = "export preview obj with terrain blocks")]
     Obj(ObjOpts),
 
@@ -30,11 +45,27 @@ struct InfoOpts {
     input_path: String,
 }
 
-fn process_info(info_opts: InfoOpts) -> anyhow::Result<()> {
-    let mut file = File::open(&info_opts.input_path)?;
-    let header: lbf::Header = lbf::Header::parse(&mut file)?;
+/// `lbf info --format json` output.
+#[derive(Serialize)]
+struct Info {
+    /// Block count stored in the header
+    block_count: u32,
+    /// Object count over all blocks stored in the header
+    block_object_count: u32,
+}
+
+fn process_info(info_opts: InfoOpts, format: OutputFormat) -> anyhow::Result<()> {
+    let (header, _) = parse_file(Path::new(&info_opts.input_path), lbf::Header::parse)?;
+
+    let info = Info {
+        block_count: header.block_count,
+        block_object_count: header.block_object_count,
+    };
 
-    println!("Block count: {}", header.block_count);
+    match format {
+        OutputFormat::Json => print_json(&info)?,
+        OutputFormat::Text => println!("Block count: {}", info.block_count),
+    }
 
     Ok(())
 }
@@ -48,24 +79,27 @@ struct ObjOpts {
 }
 
 fn process_obj(obj_opts: ObjOpts) -> anyhow::Result<()> {
-    let mut file = File::open(&obj_opts.input_path)?;
-    let lbf: lbf::Lbf = lbf::Lbf::parse(&mut file)?;
+    let input_path = Path::new(&obj_opts.input_path);
+    let (lbf, mut file) = parse_file(input_path, lbf::Lbf::parse)?;
 
     let mut obj = nif::obj::Obj::default();
 
-    for block in lbf.blocks {
+    for (block_position, block) in lbf.blocks.into_iter().enumerate() {
         for block_object in block.objects {
-            file.seek(SeekFrom::Start(block_object.file_offset as u64))?;
-
-            let mut nif_buf = vec![0u8; block_object.file_length as usize];
-            file.read_exact(&mut nif_buf)?;
+            let nif_buf = read_payload(
+                &mut file,
+                format_args!("block {} object {}", block_position, block_object.index),
+                block_object.file_offset,
+                block_object.file_length,
+            )
+            .map_err(|e| e.in_file(input_path))?;
 
             let mut nif_cursor = Cursor::new(nif_buf);
 
             let nif = match nif::Nif::parse(&mut nif_cursor) {
                 Ok(nif) => nif,
                 Err(e) => {
-                    println!(
+                    eprintln!(
                         "Failed to parse NIF for block index {} unk {}: {:?}",
                         block_object.index, block_object.unk, e
                     );
@@ -99,25 +133,32 @@ struct GltfOpts {
     output_path: String,
 }
 
-fn process_gltf(gltf_opts: GltfOpts) -> anyhow::Result<()> {
-    let mut file = File::open(&gltf_opts.input_path)?;
-    let lbf: lbf::Lbf = lbf::Lbf::parse(&mut file)?;
-
-    let mut gltf = nif::gltf::Gltf::new();
-
-    for block in lbf.blocks {
-        for block_object in block.objects {
-            file.seek(SeekFrom::Start(block_object.file_offset as u64))?;
-
-            let mut nif_buf = vec![0u8; block_object.file_length as usize];
-            file.read_exact(&mut nif_buf)?;
+/// Adds the objects of every block accepted by the filter to the
+/// "Block Objects" scene.
+pub fn visit_gltf_block_objects<F>(
+    gltf: &mut nif::gltf::Gltf,
+    file: &mut File,
+    lbf: &lbf::Lbf,
+    block_object_filter: F,
+) -> anyhow::Result<()>
+where
+    F: Fn(&lbf::BlockObject) -> bool,
+{
+    for (block_position, block) in lbf.blocks.iter().enumerate() {
+        for block_object in block.objects.iter().filter(|bo| block_object_filter(bo)) {
+            let nif_buf = read_payload(
+                file,
+                format_args!("block {} object {}", block_position, block_object.index),
+                block_object.file_offset,
+                block_object.file_length,
+            )?;
 
             let mut nif_cursor = Cursor::new(nif_buf);
 
             let nif = match nif::Nif::parse(&mut nif_cursor) {
                 Ok(nif) => nif,
                 Err(e) => {
-                    println!(
+                    eprintln!(
                         "Failed to parse NIF for block index {} unk {}: {:?}",
                         block_object.index, block_object.unk, e
                     );
@@ -133,15 +174,172 @@ fn process_gltf(gltf_opts: GltfOpts) -> anyhow::Result<()> {
         }
     }
 
+    Ok(())
+}
+
+fn process_gltf(gltf_opts: GltfOpts) -> anyhow::Result<()> {
+    let (lbf, mut file) = parse_file(Path::new(&gltf_opts.input_path), lbf::Lbf::parse)?;
+
+    let mut gltf = nif::gltf::Gltf::new();
+
+    visit_gltf_block_objects(&mut gltf, &mut file, &lbf, |_| true)?;
+
     let gltf_path = std::path::PathBuf::from(gltf_opts.output_path);
     gltf.write_to_files(gltf_path)?;
 
     Ok(())
 }
 
-pub fn process_lbf(lbf_opts: LbfOpts) -> anyhow::Result<()> {
+#[derive(Clap)]
+struct UnpackOpts {
+    #[clap(short, long, about = "input file")]
+    input_path: String,
+    #[clap(short, long, about = "output directory")]
+    output_path: String,
+}
+
+fn process_unpack(unpack_opts: UnpackOpts) -> anyhow::Result<()> {
+    unpack_file(
+        Path::new(&unpack_opts.input_path),
+        Path::new(&unpack_opts.output_path),
+    )?;
+
+    Ok(())
+}
+
+/// Nifs are named by their position in the block and object lists, block
+/// objects have no unique id of their own.
+fn nif_file_name(block_position: usize, object_position: usize) -> String {
+    format!("{}_{}.nif", block_position, object_position)
+}
+
+/// Parses the block object file at `input_path` and unpacks it into
+/// `out_dir_path`.
+pub fn unpack_file(input_path: &Path, out_dir_path: &Path) -> Result<()> {
+    let (lbf_archive, mut file) = parse_file(input_path, lbf::Lbf::parse)?;
+
+    unpack(&lbf_archive, &mut file, out_dir_path).map_err(|e| e.in_file(input_path))
+}
+
+/// Writes every block object nif and `manifest.json` into `out_dir_path`,
+/// reading the nifs from `input`.
+pub fn unpack<R: Read + Seek>(
+    lbf_archive: &lbf::Lbf,
+    input: &mut R,
+    out_dir_path: &Path,
+) -> Result<()> {
+    std::fs::create_dir_all(out_dir_path).with_path(out_dir_path)?;
+
+    {
+        let manifest_path = out_dir_path.join("manifest.json");
+        let manifest_file = File::create(&manifest_path).with_path(&manifest_path)?;
+        serde_json::to_writer_pretty(manifest_file, lbf_archive)?;
+    }
+
+    for (block_position, block) in lbf_archive.blocks.iter().enumerate() {
+        for (object_position, block_object) in block.objects.iter().enumerate() {
+            eprintln!(
+                "Writing block {} object {}",
+                block_position, object_position
+            );
+
+            let nif_buffer = read_payload(
+                input,
+                format_args!("block {} object {}", block_position, object_position),
+                block_object.file_offset,
+                block_object.file_length,
+            )?;
+
+            let nif_path = out_dir_path.join(nif_file_name(block_position, object_position));
+            let mut nif_file = File::create(&nif_path).with_path(&nif_path)?;
+            nif_file.write_all(&nif_buffer).with_path(&nif_path)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Clap)]
+struct PackOpts {
+    #[clap(short, long, about = "input manifest")]
+    input_path: String,
+    #[clap(short, long, about = "output file")]
+    output_path: String,
+}
+
+fn process_pack(pack_opts: PackOpts) -> anyhow::Result<()> {
+    pack_file(
+        Path::new(&pack_opts.input_path),
+        Path::new(&pack_opts.output_path),
+    )?;
+
+    Ok(())
+}
+
+/// Packs the manifest at `input_path` and the nifs next to it.
+pub fn pack_file(input_path: &Path, output_path: &Path) -> Result<()> {
+    let mut lbf_archive: lbf::Lbf = read_json(input_path)?;
+
+    lbf_archive.header.version_date = 20090406;
+
+    let out_file = File::create(output_path).with_path(output_path)?;
+    pack(&lbf_archive, &input_path.with_file_name(""), out_file)
+}
+
+/// Writes a block object file, nifs are read from
+/// `<block position>_<object position>.nif` in `nif_dir_path`.
+pub fn pack<W: Write>(lbf_archive: &lbf::Lbf, nif_dir_path: &Path, mut out_file: W) -> Result<()> {
+    let nif_file_paths: Vec<Vec<PathBuf>> = lbf_archive
+        .blocks
+        .iter()
+        .enumerate()
+        .map(|(block_position, block)| {
+            (0..block.objects.len())
+                .map(|object_position| {
+                    nif_dir_path.join(nif_file_name(block_position, object_position))
+                })
+                .collect()
+        })
+        .collect();
+
+    // Nifs follow the block table in order
+    let mut file_offset = lbf_archive.encoded_len()? as u32;
+    let mut blocks = Vec::with_capacity(lbf_archive.blocks.len());
+    for (block, block_file_paths) in lbf_archive.blocks.iter().zip(nif_file_paths.iter()) {
+        let mut objects = Vec::with_capacity(block.objects.len());
+        for (block_object, nif_file_path) in block.objects.iter().zip(block_file_paths.iter()) {
+            let file_length = std::fs::metadata(nif_file_path)
+                .with_path(nif_file_path)?
+                .len() as u32;
+            objects.push(lbf::BlockObject {
+                file_offset,
+                file_length,
+                ..*block_object
+            });
+            file_offset += file_length;
+        }
+        blocks.push(lbf::Block {
+            object_count: block.object_count,
+            objects,
+        });
+    }
+
+    lbf_archive.header.encode(&mut out_file)?;
+    blocks.encode(&mut out_file)?;
+
+    for nif_file_path in nif_file_paths.iter().flatten() {
+        let mut nif_file = File::open(nif_file_path).with_path(nif_file_path)?;
+        std::io::copy(&mut nif_file, &mut out_file)?;
+    }
+
+    Ok(())
+}
+
+pub fn process_lbf(lbf_opts: LbfOpts, format: OutputFormat) -> anyhow::Result<()> {
     match lbf_opts.cmd {
-        Command::Info(info_opts) => process_info(info_opts),
+        Command::Info(info_opts) => process_info(info_opts, format),
+        Command::Unpack(unpack_opts) => process_unpack(unpack_opts),
+        Command::Pack(pack_opts) => process_pack(pack_opts),
         Command::Obj(obj_opts) => process_obj(obj_opts),
         Command::Gltf(gltf_opts) => process_gltf(gltf_opts),
     }
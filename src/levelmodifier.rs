@@ -1,8 +1,23 @@
-use std::{fs::File, io::Write, path::Path};
+use std::{fs::File, path::Path};
 
 use clap::Clap;
+use serde::Serialize;
 use slidetown::parsers::levelmodifier;
 
+use crate::{
+    error::{parse_file, read_json, Error, PathContext, Result},
+    output::{is_json_path, print_json, OutputFormat},
+    Encode,
+};
+
+mod apply;
+mod diff;
+mod tables;
+
+use apply::ApplyOpts;
+use diff::DiffOpts;
+use tables::{ExportCsvOpts, ImportCsvOpts};
+
 #[derive(Clap)]
 pub struct LevelModifierOpts {
     #[clap(subcommand, about = "subcommand to run")]
@@ -11,29 +26,231 @@ pub struct LevelModifierOpts {
 
 #[derive(Clap)]
 enum Command {
-    // #[clap(about = "display info about archive contents")]
-    // Info(InfoOpts),
+    #[clap(about = "display stat tables")]
+    Info(InfoOpts),
     #[clap(about = "unpack levelmodifier and create manifest")]
     Unpack(UnpackOpts),
     #[clap(about = "pack levelmodifier using manifest")]
     Pack(PackOpts),
+    #[clap(name = "export-csv", about = "export one csv table per stat group")]
+    ExportCsv(ExportCsvOpts),
+    #[clap(name = "import-csv", about = "rebuild levelmodifier from csv tables")]
+    ImportCsv(ImportCsvOpts),
+    #[clap(about = "compare the stat tables of two levelmodifiers")]
+    Diff(DiffOpts),
+    #[clap(about = "apply bulk edit expressions and pack")]
+    Apply(ApplyOpts),
+}
+
+#[derive(Clap)]
+struct InfoOpts {
+    #[clap(short, long, about = "input file (levelmodifier or json)")]
+    input_path: String,
+    #[clap(long, about = "only show the values of this part id")]
+    id: Option<u32>,
+}
+
+/// `levelmodifier info --format json` output.
+#[derive(Serialize)]
+struct Info {
+    version_date: u32,
+    groups: Vec<GroupInfo>,
+}
+
+#[derive(Serialize)]
+struct GroupInfo {
+    name: &'static str,
+    /// Id count stored in the header
+    length: u32,
+    /// All ids, or just the one asked for with `--id`
+    ids: Vec<u32>,
+    /// One row per level with the values of `ids` in order
+    levels: Vec<Vec<Option<f32>>>,
+}
+
+/// One of the four stat groups. Every level has a row, and column `i` of a
+/// row holds the value for `ids[i]`.
+struct StatGroup<'a> {
+    name: &'static str,
+    length: u32,
+    ids: &'a [u32],
+    levels: &'a [levelmodifier::GroupOption],
+}
+
+/// Number of level rows the parser reads for each stat group.
+pub fn level_count(version_date: u32) -> usize {
+    if version_date > 20070507 {
+        1001
+    } else {
+        801
+    }
+}
+
+fn stat_groups(levelmodifier: &levelmodifier::LevelModifier) -> [StatGroup<'_>; 4] {
+    let header = &levelmodifier.header;
+    [
+        StatGroup {
+            name: "speed",
+            length: header.speed_length,
+            ids: &header.speed_ids,
+            levels: &levelmodifier.speed,
+        },
+        StatGroup {
+            name: "accel",
+            length: header.accel_length,
+            ids: &header.accel_ids,
+            levels: &levelmodifier.accel,
+        },
+        StatGroup {
+            name: "dura",
+            length: header.dura_length,
+            ids: &header.dura_ids,
+            levels: &levelmodifier.dura,
+        },
+        StatGroup {
+            name: "boost",
+            length: header.boost_length,
+            ids: &header.boost_ids,
+            levels: &levelmodifier.boost,
+        },
+    ]
+}
+
+/// Reads a levelmodifier from JSON if the path ends in `.json`, otherwise
+/// parses it.
+pub fn read_levelmodifier(path: &Path) -> Result<levelmodifier::LevelModifier> {
+    if is_json_path(path) {
+        read_json(path)
+    } else {
+        Ok(parse_file(path, levelmodifier::LevelModifier::parse)?.0)
+    }
+}
+
+/// Writes JSON if the path ends in `.json`, otherwise packs.
+pub fn write_levelmodifier_file(
+    path: &Path,
+    levelmodifier: &levelmodifier::LevelModifier,
+) -> Result<()> {
+    let mut file = File::create(path).with_path(path)?;
+
+    if is_json_path(path) {
+        serde_json::to_writer_pretty(file, levelmodifier).map_err(Error::from)
+    } else {
+        levelmodifier.encode(&mut file).map_err(Error::from)
+    }
+    .map_err(|e| e.in_file(path))
+}
+
+fn print_table(group: &StatGroup) {
+    let rows: Vec<Vec<String>> = group
+        .levels
+        .iter()
+        .map(|level| level.values.iter().map(|v| v.to_string()).collect())
+        .collect();
+
+    let level_width = rows.len().saturating_sub(1).to_string().len().max(5);
+    let value_width = rows
+        .iter()
+        .flat_map(|values| values.iter().map(String::len))
+        .chain(group.ids.iter().map(|id| id.to_string().len()))
+        .max()
+        .unwrap_or(0);
+
+    print!("  {:>width$} |", "level", width = level_width);
+    for id in group.ids.iter() {
+        print!(" {:>width$}", id, width = value_width);
+    }
+    println!();
+
+    for (level, values) in rows.iter().enumerate() {
+        print!("  {:>width$} |", level, width = level_width);
+        for value in values.iter() {
+            print!(" {:>width$}", value, width = value_width);
+        }
+        println!();
+    }
 }
 
-// #[derive(Clap)]
-// struct InfoOpts {
-//     #[clap(short, long, about = "input file")]
-//     input_path: String,
-// }
+fn group_info(group: &StatGroup, id: Option<u32>) -> GroupInfo {
+    let columns: Vec<usize> = match id {
+        Some(id) => group
+            .ids
+            .iter()
+            .position(|&i| i == id)
+            .into_iter()
+            .collect(),
+        None => (0..group.ids.len()).collect(),
+    };
 
-// fn process_info(info_opts: InfoOpts) -> anyhow::Result<()> {
-//     let mut file = File::open(&info_opts.input_path)?;
-//     let header: lf::Header = lf::Header::parse(&mut file)?;
+    GroupInfo {
+        name: group.name,
+        length: group.length,
+        ids: columns.iter().map(|&column| group.ids[column]).collect(),
+        levels: group
+            .levels
+            .iter()
+            .map(|level| {
+                columns
+                    .iter()
+                    .map(|&column| level.values.get(column).copied())
+                    .collect()
+            })
+            .collect(),
+    }
+}
 
-//     println!("Dimensions: {}x{}", header.size_x, header.size_y);
-//     println!("Block count: {}", header.block_count);
+fn process_info(info_opts: InfoOpts, format: OutputFormat) -> anyhow::Result<()> {
+    let levelmodifier = read_levelmodifier(Path::new(&info_opts.input_path))?;
 
-//     Ok(())
-// }
+    if format == OutputFormat::Json {
+        let info = Info {
+            version_date: levelmodifier.header.version_date,
+            groups: stat_groups(&levelmodifier)
+                .iter()
+                .map(|group| group_info(group, info_opts.id))
+                .collect(),
+        };
+        return print_json(&info);
+    }
+
+    println!("Version date: {}", levelmodifier.header.version_date);
+
+    for group in stat_groups(&levelmodifier).iter() {
+        println!();
+        println!(
+            "[{}] length {}, {} ids, {} levels",
+            group.name,
+            group.length,
+            group.ids.len(),
+            group.levels.len()
+        );
+
+        match info_opts.id {
+            Some(id) => match group.ids.iter().position(|&i| i == id) {
+                Some(column) => {
+                    let values: Vec<String> = group
+                        .levels
+                        .iter()
+                        .map(|level| {
+                            level
+                                .values
+                                .get(column)
+                                .map_or_else(|| "?".to_string(), |v| v.to_string())
+                        })
+                        .collect();
+                    println!("  {}: [{}]", id, values.join(", "));
+                }
+                None => println!("  {}: not present", id),
+            },
+            None => {
+                println!("  ids: {:?}", group.ids);
+                print_table(group);
+            }
+        }
+    }
+
+    Ok(())
+}
 
 #[derive(Clap)]
 struct UnpackOpts {
@@ -44,18 +261,15 @@ struct UnpackOpts {
 }
 
 fn process_unpack(unpack_opts: UnpackOpts) -> anyhow::Result<()> {
-    let mut file = File::open(&unpack_opts.input_path).expect("Failed to open source file");
-
-    let levelmodifier: levelmodifier::LevelModifier =
-        levelmodifier::LevelModifier::parse(&mut file).expect("Failed to parse source file");
+    let (levelmodifier, _) = parse_file(
+        Path::new(&unpack_opts.input_path),
+        levelmodifier::LevelModifier::parse,
+    )?;
 
     let out_path = Path::new(&unpack_opts.output_path);
-
-    {
-        let json_file = File::create(out_path).expect("Failed to open target file");
-        serde_json::to_writer_pretty(json_file, &levelmodifier)
-            .expect("Failed to write to target file");
-    }
+    let json_file = File::create(out_path).with_path(out_path)?;
+    serde_json::to_writer_pretty(json_file, &levelmodifier)
+        .map_err(|e| Error::from(e).in_file(out_path))?;
 
     Ok(())
 }
@@ -71,66 +285,79 @@ struct PackOpts {
 fn process_pack(pack_opts: PackOpts) -> anyhow::Result<()> {
     let input_path = Path::new(&pack_opts.input_path);
 
-    let levelmodifier: levelmodifier::LevelModifier = {
-        let levelmodifier_json = File::open(input_path)?;
-        serde_json::from_reader(levelmodifier_json)?
-    };
-
-    let mut out_file = File::create(pack_opts.output_path)?;
-    out_file.write_all(b"DPDB")?;
-    out_file.write_all(&levelmodifier.header.version_date.to_le_bytes())?;
-    out_file.write_all(&levelmodifier.header.speed_length.to_le_bytes())?;
-    out_file.write_all(&levelmodifier.header.accel_length.to_le_bytes())?;
-    out_file.write_all(&levelmodifier.header.dura_length.to_le_bytes())?;
-    out_file.write_all(&levelmodifier.header.boost_length.to_le_bytes())?;
+    let mut levelmodifier: levelmodifier::LevelModifier = read_json(input_path)?;
 
-    for id in levelmodifier.header.speed_ids {
-        out_file.write_all(&id.to_le_bytes())?;
-    }
+    check_and_update_lengths(&mut levelmodifier)?;
 
-    for id in levelmodifier.header.accel_ids {
-        out_file.write_all(&id.to_le_bytes())?;
-    }
+    let out_path = Path::new(&pack_opts.output_path);
+    let mut out_file = File::create(out_path).with_path(out_path)?;
+    levelmodifier.encode(&mut out_file).with_path(out_path)?;
 
-    for id in levelmodifier.header.dura_ids {
-        out_file.write_all(&id.to_le_bytes())?;
-    }
+    Ok(())
+}
 
-    for id in levelmodifier.header.boost_ids {
-        out_file.write_all(&id.to_le_bytes())?;
-    }
+/// Every group needs a row per level, and every row needs a value per id.
+/// The header lengths are derived from the id lists.
+pub fn check_and_update_lengths(levelmodifier: &mut levelmodifier::LevelModifier) -> Result<()> {
+    let mut issues = Vec::new();
+    let expected_levels = level_count(levelmodifier.header.version_date);
 
-    for option in levelmodifier.speed {
-        for value in option.values {
-            out_file.write_all(&value.to_le_bytes())?;
+    for group in stat_groups(levelmodifier).iter() {
+        if group.levels.len() != expected_levels {
+            issues.push(format!(
+                "{}: {} level rows, expected {} for version date {}",
+                group.name,
+                group.levels.len(),
+                expected_levels,
+                levelmodifier.header.version_date
+            ));
         }
-    }
 
-    for option in levelmodifier.accel {
-        for value in option.values {
-            out_file.write_all(&value.to_le_bytes())?;
+        for (level, row) in group.levels.iter().enumerate() {
+            if row.values.len() != group.ids.len() {
+                issues.push(format!(
+                    "{}: level {} has {} values, expected one per id ({})",
+                    group.name,
+                    level,
+                    row.values.len(),
+                    group.ids.len()
+                ));
+            }
         }
     }
 
-    for option in levelmodifier.dura {
-        for value in option.values {
-            out_file.write_all(&value.to_le_bytes())?;
-        }
+    if !issues.is_empty() {
+        return Err(Error::Inconsistent(issues));
     }
 
-    for option in levelmodifier.boost {
-        for value in option.values {
-            out_file.write_all(&value.to_le_bytes())?;
+    let header = &mut levelmodifier.header;
+    for (name, length, ids) in [
+        ("speed_length", &mut header.speed_length, &header.speed_ids),
+        ("accel_length", &mut header.accel_length, &header.accel_ids),
+        ("dura_length", &mut header.dura_length, &header.dura_ids),
+        ("boost_length", &mut header.boost_length, &header.boost_ids),
+    ] {
+        let id_count = ids.len() as u32;
+        if *length != id_count {
+            eprintln!("Updating {} from {} to {}", name, length, id_count);
+            *length = id_count;
         }
     }
 
     Ok(())
 }
 
-pub fn process_levelmodifier(levelmodifier_opts: LevelModifierOpts) -> anyhow::Result<()> {
+pub fn process_levelmodifier(
+    levelmodifier_opts: LevelModifierOpts,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
     match levelmodifier_opts.cmd {
-        // Command::Info(info_opts) => process_info(info_opts),
+        Command::Info(info_opts) => process_info(info_opts, format),
         Command::Unpack(unpack_opts) => process_unpack(unpack_opts),
         Command::Pack(pack_opts) => process_pack(pack_opts),
+        Command::ExportCsv(export_csv_opts) => tables::process_export_csv(export_csv_opts),
+        Command::ImportCsv(import_csv_opts) => tables::process_import_csv(import_csv_opts),
+        Command::Diff(diff_opts) => diff::process_diff(diff_opts),
+        Command::Apply(apply_opts) => apply::process_apply(apply_opts),
     }
 }
@@ -0,0 +1,268 @@
+use std::{path::Path, str::FromStr};
+
+use anyhow::Context;
+use clap::Clap;
+use slidetown::parsers::levelmodifier;
+
+#[derive(Clap)]
+pub struct ApplyOpts {
+    #[clap(short, long, about = "input file (levelmodifier or json)")]
+    input_path: String,
+    #[clap(short, long, about = "output file (levelmodifier or json)")]
+    output_path: Option<String>,
+    #[clap(
+        short,
+        long,
+        about = "edit like 'boost[*] *= 1.05' or 'speed[1203][3] = 120' (ids then levels), can be repeated"
+    )]
+    expr: Vec<Expression>,
+    #[clap(long, about = "file with one expression per line, # starts a comment")]
+    script_path: Option<String>,
+    #[clap(long, about = "only print the changes")]
+    dry_run: bool,
+}
+
+/// `*`, or a comma separated list of `N` and `START-END`.
+enum Selector {
+    All,
+    Ranges(Vec<(u32, u32)>),
+}
+
+impl FromStr for Selector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s == "*" {
+            return Ok(Selector::All);
+        }
+
+        let ranges = s
+            .split(',')
+            .map(|part| {
+                let part = part.trim();
+                let (start, end) = match part.split_once('-') {
+                    Some((start, end)) => (start.trim().parse()?, end.trim().parse()?),
+                    None => {
+                        let index = part.parse()?;
+                        (index, index)
+                    }
+                };
+                anyhow::ensure!(start <= end, "range {:?} is reversed", part);
+                Ok((start, end))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+            .with_context(|| format!("invalid selector {:?}", s))?;
+
+        Ok(Selector::Ranges(ranges))
+    }
+}
+
+impl Selector {
+    fn contains(&self, value: u32) -> bool {
+        match self {
+            Selector::All => true,
+            Selector::Ranges(ranges) => ranges
+                .iter()
+                .any(|&(start, end)| (start..=end).contains(&value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Operator {
+    Set,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl Operator {
+    fn apply(self, value: f32, operand: f32) -> f32 {
+        match self {
+            Operator::Set => operand,
+            Operator::Add => value + operand,
+            Operator::Subtract => value - operand,
+            Operator::Multiply => value * operand,
+            Operator::Divide => value / operand,
+        }
+    }
+}
+
+/// `group[ids][levels] op value`, the group can be `*` for all four and the
+/// level selector is optional.
+pub struct Expression {
+    source: String,
+    group: Option<String>,
+    ids: Selector,
+    levels: Selector,
+    operator: Operator,
+    operand: f32,
+}
+
+impl FromStr for Expression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let target_end = s
+            .rfind(']')
+            .with_context(|| format!("expected group[ids] in {:?}", s))?;
+        let (target, assignment) = s.split_at(target_end + 1);
+        let target = target.trim();
+        let assignment = assignment.trim();
+
+        let operators = [
+            ("+=", Operator::Add),
+            ("-=", Operator::Subtract),
+            ("*=", Operator::Multiply),
+            ("/=", Operator::Divide),
+            ("=", Operator::Set),
+        ];
+        let (operator, operand) = operators
+            .iter()
+            .find_map(|(token, operator)| {
+                assignment
+                    .strip_prefix(token)
+                    .map(|operand| (*operator, operand.trim()))
+            })
+            .with_context(|| format!("expression {:?} has no =, +=, -=, *= or /=", s))?;
+
+        let operand = operand
+            .parse()
+            .with_context(|| format!("invalid number {:?} in {:?}", operand, s))?;
+
+        let (group, selectors) = target
+            .split_once('[')
+            .with_context(|| format!("expected group[ids] in {:?}", s))?;
+        let group = match group.trim() {
+            "*" => None,
+            "speed" | "accel" | "dura" | "boost" => Some(group.trim().to_string()),
+            other => anyhow::bail!("unknown stat group {:?} in {:?}", other, s),
+        };
+
+        let selectors: Vec<&str> = selectors
+            .strip_suffix(']')
+            .unwrap_or(selectors)
+            .split("][")
+            .collect();
+        let (ids, levels) = match selectors.as_slice() {
+            [ids] => (ids.parse()?, Selector::All),
+            [ids, levels] => (ids.parse()?, levels.parse()?),
+            _ => anyhow::bail!("expected at most two selectors in {:?}", s),
+        };
+
+        Ok(Expression {
+            source: s.trim().to_string(),
+            group,
+            ids,
+            levels,
+            operator,
+            operand,
+        })
+    }
+}
+
+fn group_mut<'a>(
+    levelmodifier: &'a mut levelmodifier::LevelModifier,
+    name: &str,
+) -> (&'a [u32], &'a mut Vec<levelmodifier::GroupOption>) {
+    let header = &levelmodifier.header;
+    match name {
+        "speed" => (&header.speed_ids, &mut levelmodifier.speed),
+        "accel" => (&header.accel_ids, &mut levelmodifier.accel),
+        "dura" => (&header.dura_ids, &mut levelmodifier.dura),
+        _ => (&header.boost_ids, &mut levelmodifier.boost),
+    }
+}
+
+/// Applies one expression, printing every changed value. Returns how many
+/// values were touched.
+fn apply_expression(
+    levelmodifier: &mut levelmodifier::LevelModifier,
+    expression: &Expression,
+) -> usize {
+    let group_names: Vec<&str> = match &expression.group {
+        Some(group) => vec![group.as_str()],
+        None => vec!["speed", "accel", "dura", "boost"],
+    };
+
+    let mut touched = 0;
+
+    for group_name in group_names {
+        let (ids, group) = group_mut(levelmodifier, group_name);
+
+        for (level, row) in group.iter_mut().enumerate() {
+            if !expression.levels.contains(level as u32) {
+                continue;
+            }
+
+            // each id owns the value column at its position in the id list
+            for (id, value) in ids.iter().zip(row.values.iter_mut()) {
+                if !expression.ids.contains(*id) {
+                    continue;
+                }
+
+                let new_value = expression.operator.apply(*value, expression.operand);
+                if new_value != *value {
+                    println!(
+                        "  {}[{}][{}]: {} -> {}",
+                        group_name, id, level, value, new_value
+                    );
+                }
+                *value = new_value;
+                touched += 1;
+            }
+        }
+    }
+
+    touched
+}
+
+pub fn process_apply(apply_opts: ApplyOpts) -> anyhow::Result<()> {
+    let mut levelmodifier = super::read_levelmodifier(Path::new(&apply_opts.input_path))?;
+
+    let mut expressions = apply_opts.expr;
+    if let Some(script_path) = &apply_opts.script_path {
+        let script = std::fs::read_to_string(script_path)?;
+        for (line_index, line) in script.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            expressions.push(
+                line.parse()
+                    .with_context(|| format!("{} line {}", script_path, line_index + 1))?,
+            );
+        }
+    }
+
+    anyhow::ensure!(
+        !expressions.is_empty(),
+        "nothing to apply, pass --expr or --script-path"
+    );
+
+    for expression in expressions.iter() {
+        println!("{}", expression.source);
+        let touched = apply_expression(&mut levelmodifier, expression);
+        if touched == 0 {
+            println!("  matched no values");
+        }
+    }
+
+    if apply_opts.dry_run {
+        eprintln!("Dry run, nothing written");
+        return Ok(());
+    }
+
+    let output_path = apply_opts
+        .output_path
+        .context("--output-path is required unless --dry-run is given")?;
+
+    super::check_and_update_lengths(&mut levelmodifier)?;
+    super::write_levelmodifier_file(Path::new(&output_path), &levelmodifier)?;
+
+    eprintln!("Wrote {}", output_path);
+
+    Ok(())
+}
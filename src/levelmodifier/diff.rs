@@ -0,0 +1,266 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::Write,
+    path::Path,
+    str::FromStr,
+};
+
+use clap::Clap;
+use serde::Serialize;
+
+use crate::error::PathContext;
+
+#[derive(Clap)]
+pub struct DiffOpts {
+    #[clap(about = "old file (levelmodifier or json)")]
+    old_path: String,
+    #[clap(about = "new file (levelmodifier or json)")]
+    new_path: String,
+    #[clap(
+        long,
+        default_value = "text",
+        about = "report format: text, markdown or json"
+    )]
+    report_format: ReportFormat,
+    #[clap(short, long, about = "write the report to this file instead of stdout")]
+    output_path: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum ReportFormat {
+    Text,
+    Markdown,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ReportFormat::Text),
+            "markdown" | "md" => Ok(ReportFormat::Markdown),
+            "json" => Ok(ReportFormat::Json),
+            _ => anyhow::bail!("unknown report format {:?}", s),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ValueChange {
+    id: u32,
+    level: usize,
+    old: Option<f32>,
+    new: Option<f32>,
+    /// Relative change in percent, absent when either side is missing or
+    /// the old value is zero
+    percent: Option<f32>,
+}
+
+#[derive(Serialize)]
+struct GroupDiff {
+    name: &'static str,
+    added_ids: Vec<u32>,
+    removed_ids: Vec<u32>,
+    changes: Vec<ValueChange>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    old_version_date: u32,
+    new_version_date: u32,
+    groups: Vec<GroupDiff>,
+}
+
+fn diff_group(old: &super::StatGroup, new: &super::StatGroup) -> GroupDiff {
+    // every id's values across all levels, so ids can be matched up even
+    // when their columns moved between the two files
+    let rows = |group: &super::StatGroup| -> BTreeMap<u32, Vec<f32>> {
+        group
+            .ids
+            .iter()
+            .enumerate()
+            .map(|(column, &id)| {
+                let values = group
+                    .levels
+                    .iter()
+                    .filter_map(|level| level.values.get(column).copied())
+                    .collect();
+                (id, values)
+            })
+            .collect()
+    };
+    let old_rows = rows(old);
+    let new_rows = rows(new);
+
+    let old_ids: BTreeSet<u32> = old_rows.keys().copied().collect();
+    let new_ids: BTreeSet<u32> = new_rows.keys().copied().collect();
+
+    let mut changes = Vec::new();
+    for id in old_ids.intersection(&new_ids) {
+        let old_values = &old_rows[id];
+        let new_values = &new_rows[id];
+
+        for level in 0..old_values.len().max(new_values.len()) {
+            let old_value = old_values.get(level).copied();
+            let new_value = new_values.get(level).copied();
+            if old_value == new_value {
+                continue;
+            }
+
+            let percent = match (old_value, new_value) {
+                (Some(old_value), Some(new_value)) if old_value != 0.0 => {
+                    Some((new_value - old_value) / old_value.abs() * 100.0)
+                }
+                _ => None,
+            };
+
+            changes.push(ValueChange {
+                id: *id,
+                level,
+                old: old_value,
+                new: new_value,
+                percent,
+            });
+        }
+    }
+
+    GroupDiff {
+        name: old.name,
+        added_ids: new_ids.difference(&old_ids).copied().collect(),
+        removed_ids: old_ids.difference(&new_ids).copied().collect(),
+        changes,
+    }
+}
+
+fn format_value(value: Option<f32>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| v.to_string())
+}
+
+fn format_percent(percent: Option<f32>) -> String {
+    percent.map_or_else(String::new, |p| format!("{:+.2}%", p))
+}
+
+fn write_text<W: Write>(out: &mut W, report: &Report) -> anyhow::Result<()> {
+    writeln!(
+        out,
+        "Version date: {} -> {}",
+        report.old_version_date, report.new_version_date
+    )?;
+
+    for group in report.groups.iter() {
+        writeln!(out)?;
+        writeln!(
+            out,
+            "[{}] {} added, {} removed, {} values changed",
+            group.name,
+            group.added_ids.len(),
+            group.removed_ids.len(),
+            group.changes.len()
+        )?;
+        if !group.added_ids.is_empty() {
+            writeln!(out, "  added ids: {:?}", group.added_ids)?;
+        }
+        if !group.removed_ids.is_empty() {
+            writeln!(out, "  removed ids: {:?}", group.removed_ids)?;
+        }
+        for change in group.changes.iter() {
+            writeln!(
+                out,
+                "  {}[{}]: {} -> {} {}",
+                change.id,
+                change.level,
+                format_value(change.old),
+                format_value(change.new),
+                format_percent(change.percent)
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_markdown<W: Write>(out: &mut W, report: &Report) -> anyhow::Result<()> {
+    writeln!(out, "# Level modifier changes")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "Version date: `{}` -> `{}`",
+        report.old_version_date, report.new_version_date
+    )?;
+
+    for group in report.groups.iter() {
+        if group.added_ids.is_empty() && group.removed_ids.is_empty() && group.changes.is_empty() {
+            continue;
+        }
+
+        writeln!(out)?;
+        writeln!(out, "## {}", group.name)?;
+        writeln!(out)?;
+
+        let ids = |ids: &[u32]| {
+            ids.iter()
+                .map(|id| format!("`{}`", id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        if !group.added_ids.is_empty() {
+            writeln!(out, "Added ids: {}", ids(&group.added_ids))?;
+            writeln!(out)?;
+        }
+        if !group.removed_ids.is_empty() {
+            writeln!(out, "Removed ids: {}", ids(&group.removed_ids))?;
+            writeln!(out)?;
+        }
+
+        if !group.changes.is_empty() {
+            writeln!(out, "| Id | Level | Old | New | Change |")?;
+            writeln!(out, "| --: | --: | --: | --: | --: |")?;
+            for change in group.changes.iter() {
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} | {} |",
+                    change.id,
+                    change.level,
+                    format_value(change.old),
+                    format_value(change.new),
+                    format_percent(change.percent)
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn process_diff(diff_opts: DiffOpts) -> anyhow::Result<()> {
+    let old = super::read_levelmodifier(Path::new(&diff_opts.old_path))?;
+    let new = super::read_levelmodifier(Path::new(&diff_opts.new_path))?;
+
+    let report = Report {
+        old_version_date: old.header.version_date,
+        new_version_date: new.header.version_date,
+        groups: super::stat_groups(&old)
+            .iter()
+            .zip(super::stat_groups(&new).iter())
+            .map(|(old_group, new_group)| diff_group(old_group, new_group))
+            .collect(),
+    };
+
+    let mut out: Box<dyn Write> = match &diff_opts.output_path {
+        Some(output_path) => Box::new(File::create(output_path).with_path(Path::new(output_path))?),
+        None => Box::new(std::io::stdout()),
+    };
+
+    match diff_opts.report_format {
+        ReportFormat::Text => write_text(&mut out, &report)?,
+        ReportFormat::Markdown => write_markdown(&mut out, &report)?,
+        ReportFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, &report)?;
+            writeln!(out)?;
+        }
+    }
+
+    Ok(())
+}
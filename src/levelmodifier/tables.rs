@@ -0,0 +1,214 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::Path,
+};
+
+use anyhow::Context;
+use clap::Clap;
+use slidetown::parsers::levelmodifier;
+
+#[derive(Clap)]
+pub struct ExportCsvOpts {
+    #[clap(short, long, about = "input file (levelmodifier or json)")]
+    input_path: String,
+    #[clap(
+        short,
+        long,
+        about = "output directory for speed.csv, accel.csv, dura.csv and boost.csv"
+    )]
+    output_path: String,
+}
+
+#[derive(Clap)]
+pub struct ImportCsvOpts {
+    #[clap(
+        short,
+        long,
+        about = "directory with speed.csv, accel.csv, dura.csv and boost.csv"
+    )]
+    input_path: String,
+    #[clap(
+        short,
+        long,
+        about = "levelmodifier the tables were exported from (levelmodifier or json)"
+    )]
+    base_path: String,
+    #[clap(short, long, about = "output file (levelmodifier or json)")]
+    output_path: String,
+}
+
+pub fn process_export_csv(export_csv_opts: ExportCsvOpts) -> anyhow::Result<()> {
+    let levelmodifier = super::read_levelmodifier(Path::new(&export_csv_opts.input_path))?;
+
+    let out_dir_path = Path::new(&export_csv_opts.output_path);
+    std::fs::create_dir_all(out_dir_path)?;
+
+    for group in super::stat_groups(&levelmodifier).iter() {
+        let table_path = out_dir_path.join(format!("{}.csv", group.name));
+        let mut writer = csv::Writer::from_path(table_path)?;
+
+        let levels = (0..group.levels.len()).map(|i| i.to_string());
+        writer.write_record(std::iter::once("id".to_string()).chain(levels))?;
+
+        for (column, id) in group.ids.iter().enumerate() {
+            let values = group.levels.iter().map(|level| {
+                level
+                    .values
+                    .get(column)
+                    .map_or_else(String::new, |v| v.to_string())
+            });
+            writer.write_record(std::iter::once(id.to_string()).chain(values))?;
+        }
+
+        writer.flush()?;
+
+        eprintln!(
+            "Exported {} ids over {} levels to {}.csv",
+            group.ids.len(),
+            group.levels.len(),
+            group.name
+        );
+    }
+
+    Ok(())
+}
+
+/// Reads one stat table and turns it back into level rows with the values in
+/// the order of `ids`. The ids in the table have to match `ids` exactly and
+/// every id needs a value for each of the `level_count` levels.
+fn read_table(
+    path: &Path,
+    ids: &[u32],
+    level_count: usize,
+) -> anyhow::Result<Vec<levelmodifier::GroupOption>> {
+    let mut reader = csv::Reader::from_path(path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+
+    let column_count = reader.headers()?.len();
+    anyhow::ensure!(
+        column_count > 0 && reader.headers()?.get(0).map(str::trim) == Some("id"),
+        "{}: first column has to be id",
+        path.display()
+    );
+    anyhow::ensure!(
+        column_count == level_count + 1,
+        "{}: {} level columns, expected {}",
+        path.display(),
+        column_count - 1,
+        level_count
+    );
+
+    let mut rows: HashMap<u32, Vec<f32>> = HashMap::new();
+
+    for (row_index, record) in reader.records().enumerate() {
+        // header is line 1
+        let line = row_index + 2;
+        let record = record?;
+
+        let id_value = record.get(0).unwrap_or("").trim();
+        let id: u32 = id_value.parse().with_context(|| {
+            format!(
+                "{} line {}: invalid id {:?}",
+                path.display(),
+                line,
+                id_value
+            )
+        })?;
+
+        anyhow::ensure!(
+            record.len() == column_count,
+            "{} line {}: id {} has {} columns, expected {}",
+            path.display(),
+            line,
+            id,
+            record.len(),
+            column_count
+        );
+
+        let values = record
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(column, value)| {
+                value.trim().parse::<f32>().with_context(|| {
+                    format!(
+                        "{} line {}: invalid value {:?} in column {}",
+                        path.display(),
+                        line,
+                        value,
+                        column - 1
+                    )
+                })
+            })
+            .collect::<anyhow::Result<Vec<f32>>>()?;
+
+        anyhow::ensure!(
+            rows.insert(id, values).is_none(),
+            "{} line {}: id {} appears more than once",
+            path.display(),
+            line,
+            id
+        );
+    }
+
+    let expected: BTreeSet<u32> = ids.iter().copied().collect();
+    let actual: BTreeSet<u32> = rows.keys().copied().collect();
+    let missing: Vec<&u32> = expected.difference(&actual).collect();
+    let extra: Vec<&u32> = actual.difference(&expected).collect();
+
+    if !missing.is_empty() || !extra.is_empty() {
+        anyhow::bail!(
+            "{}: id set does not match the base, missing rows for {:?}, extra rows for {:?}",
+            path.display(),
+            missing,
+            extra
+        );
+    }
+
+    let columns: Vec<Vec<f32>> = ids
+        .iter()
+        .map(|id| rows.remove(id).unwrap_or_default())
+        .collect();
+
+    Ok((0..level_count)
+        .map(|level| levelmodifier::GroupOption {
+            values: columns.iter().map(|column| column[level]).collect(),
+        })
+        .collect())
+}
+
+pub fn process_import_csv(import_csv_opts: ImportCsvOpts) -> anyhow::Result<()> {
+    let mut levelmodifier = super::read_levelmodifier(Path::new(&import_csv_opts.base_path))?;
+    let in_dir_path = Path::new(&import_csv_opts.input_path);
+
+    let header = &levelmodifier.header;
+    let level_count = super::level_count(header.version_date);
+    let speed = read_table(
+        &in_dir_path.join("speed.csv"),
+        &header.speed_ids,
+        level_count,
+    )?;
+    let accel = read_table(
+        &in_dir_path.join("accel.csv"),
+        &header.accel_ids,
+        level_count,
+    )?;
+    let dura = read_table(&in_dir_path.join("dura.csv"), &header.dura_ids, level_count)?;
+    let boost = read_table(
+        &in_dir_path.join("boost.csv"),
+        &header.boost_ids,
+        level_count,
+    )?;
+
+    levelmodifier.speed = speed;
+    levelmodifier.accel = accel;
+    levelmodifier.dura = dura;
+    levelmodifier.boost = boost;
+
+    super::check_and_update_lengths(&mut levelmodifier)?;
+    super::write_levelmodifier_file(Path::new(&import_csv_opts.output_path), &levelmodifier)?;
+
+    eprintln!("Imported stat tables into {}", import_csv_opts.output_path);
+
+    Ok(())
+}
@@ -1,13 +1,20 @@
 use std::{
-    convert::TryInto,
     fs::File,
-    io::{Cursor, Read, Seek, SeekFrom, Write},
-    path::Path,
+    io::{Cursor, Read, Seek, Write},
+    path::{Path, PathBuf},
 };
 
 use clap::Clap;
+use serde::Serialize;
 use slidetown::parsers::lf;
 
+use crate::{
+    error::{parse_file, read_json, PathContext, Result},
+    output::{print_json, OutputFormat},
+    payload::read_payload,
+    Encode,
+};
+
 #[derive(Clap)]
 pub struct LfOpts {
     #[clap(subcommand, about = "subcommand to run")]
@@ -38,12 +45,33 @@ struct InfoOpts {
     input_path: String,
 }
 
-fn process_info(info_opts: InfoOpts) -> anyhow::Result<()> {
-    let mut file = File::open(&info_opts.input_path)?;
-    let header: lf::Header = lf::Header::parse(&mut file)?;
+/// `lf info --format json` output.
+#[derive(Serialize)]
+struct Info {
+    /// Terrain grid width in blocks
+    size_x: u32,
+    /// Terrain grid height in blocks
+    size_y: u32,
+    /// Block count stored in the header
+    block_count: u32,
+}
+
+fn process_info(info_opts: InfoOpts, format: OutputFormat) -> anyhow::Result<()> {
+    let (header, _) = parse_file(Path::new(&info_opts.input_path), lf::Header::parse)?;
 
-    println!("Dimensions: {}x{}", header.size_x, header.size_y);
-    println!("Block count: {}", header.block_count);
+    let info = Info {
+        size_x: header.size_x,
+        size_y: header.size_y,
+        block_count: header.block_count,
+    };
+
+    match format {
+        OutputFormat::Json => print_json(&info)?,
+        OutputFormat::Text => {
+            println!("Dimensions: {}x{}", info.size_x, info.size_y);
+            println!("Block count: {}", info.block_count);
+        }
+    }
 
     Ok(())
 }
@@ -57,23 +85,26 @@ struct ObjOpts {
 }
 
 fn process_obj(obj_opts: ObjOpts) -> anyhow::Result<()> {
-    let mut file = File::open(&obj_opts.input_path)?;
-    let lf: lf::Lf = lf::Lf::parse(&mut file)?;
+    let input_path = Path::new(&obj_opts.input_path);
+    let (lf, mut file) = parse_file(input_path, lf::Lf::parse)?;
 
     let mut obj = nif::obj::Obj::default();
 
     for block in lf.blocks {
-        file.seek(SeekFrom::Start(block.file_offset as u64))?;
-
-        let mut nif_buf = vec![0u8; block.file_length as usize];
-        file.read_exact(&mut nif_buf)?;
+        let nif_buf = read_payload(
+            &mut file,
+            format_args!("block {}", block.index),
+            block.file_offset,
+            block.file_length,
+        )
+        .map_err(|e| e.in_file(input_path))?;
 
         let mut nif_cursor = Cursor::new(nif_buf);
 
         let nif = match nif::Nif::parse(&mut nif_cursor) {
             Ok(nif) => nif,
             Err(e) => {
-                println!(
+                eprintln!(
                     "Failed to parse NIF for block x{} y{}: {:?}",
                     block.position_x, block.position_y, e
                 );
@@ -100,24 +131,30 @@ struct GltfOpts {
     output_path: String,
 }
 
-fn process_gltf(gltf_opts: GltfOpts) -> anyhow::Result<()> {
-    let mut file = File::open(&gltf_opts.input_path)?;
-    let lf: lf::Lf = lf::Lf::parse(&mut file)?;
-
-    let mut gltf = nif::gltf::Gltf::new();
-
-    for block in lf.blocks {
-        file.seek(SeekFrom::Start(block.file_offset as u64))?;
-
-        let mut nif_buf = vec![0u8; block.file_length as usize];
-        file.read_exact(&mut nif_buf)?;
+/// Adds every terrain block accepted by the filter to the "Terrain" scene.
+pub fn visit_gltf_blocks<F>(
+    gltf: &mut nif::gltf::Gltf,
+    file: &mut File,
+    lf: &lf::Lf,
+    block_filter: F,
+) -> anyhow::Result<()>
+where
+    F: Fn(&lf::Block) -> bool,
+{
+    for block in lf.blocks.iter().filter(|block| block_filter(block)) {
+        let nif_buf = read_payload(
+            file,
+            format_args!("block {}", block.index),
+            block.file_offset,
+            block.file_length,
+        )?;
 
         let mut nif_cursor = Cursor::new(nif_buf);
 
         let nif = match nif::Nif::parse(&mut nif_cursor) {
             Ok(nif) => nif,
             Err(e) => {
-                println!(
+                eprintln!(
                     "Failed to parse NIF for block x{} y{}: {:?}",
                     block.position_x, block.position_y, e
                 );
@@ -128,6 +165,16 @@ fn process_gltf(gltf_opts: GltfOpts) -> anyhow::Result<()> {
         gltf.visit_nif(&nif, Some("Terrain"), &format!("Block{}", block.index));
     }
 
+    Ok(())
+}
+
+fn process_gltf(gltf_opts: GltfOpts) -> anyhow::Result<()> {
+    let (lf, mut file) = parse_file(Path::new(&gltf_opts.input_path), lf::Lf::parse)?;
+
+    let mut gltf = nif::gltf::Gltf::new();
+
+    visit_gltf_blocks(&mut gltf, &mut file, &lf, |_| true)?;
+
     let gltf_path = std::path::PathBuf::from(gltf_opts.output_path);
 
     gltf.write_to_files(gltf_path)?;
@@ -144,34 +191,49 @@ struct UnpackOpts {
 }
 
 fn process_unpack(unpack_opts: UnpackOpts) -> anyhow::Result<()> {
-    let mut file = File::open(&unpack_opts.input_path)?;
+    unpack_file(
+        Path::new(&unpack_opts.input_path),
+        Path::new(&unpack_opts.output_path),
+    )?;
 
-    let lf_archive: lf::Lf = lf::Lf::parse(&mut file)?;
+    Ok(())
+}
 
-    let out_dir_path = Path::new(&unpack_opts.output_path);
-    std::fs::create_dir_all(out_dir_path)?;
+/// Parses the terrain file at `input_path` and unpacks it into `out_dir_path`.
+pub fn unpack_file(input_path: &Path, out_dir_path: &Path) -> Result<()> {
+    let (lf_archive, mut file) = parse_file(input_path, lf::Lf::parse)?;
 
-    {
-        let manifest_file = File::create(out_dir_path.join("manifest.json"))?;
-        serde_json::to_writer_pretty(manifest_file, &lf_archive)?;
-    }
+    unpack(&lf_archive, &mut file, out_dir_path).map_err(|e| e.in_file(input_path))
+}
 
-    for lf_block in lf_archive.blocks {
-        println!("Writing block {}", lf_block.index);
+/// Writes every block nif and `manifest.json` into `out_dir_path`, reading
+/// the nifs from `input`.
+pub fn unpack<R: Read + Seek>(
+    lf_archive: &lf::Lf,
+    input: &mut R,
+    out_dir_path: &Path,
+) -> Result<()> {
+    std::fs::create_dir_all(out_dir_path).with_path(out_dir_path)?;
 
-        let nif_position: u64 = lf_block.file_offset.into();
-        let nif_length: usize = lf_block
-            .file_length
-            .try_into()
-            .expect("Block file size too high");
+    {
+        let manifest_path = out_dir_path.join("manifest.json");
+        let manifest_file = File::create(&manifest_path).with_path(&manifest_path)?;
+        serde_json::to_writer_pretty(manifest_file, lf_archive)?;
+    }
 
-        let mut nif_buffer = vec![0u8; nif_length];
+    for lf_block in lf_archive.blocks.iter() {
+        eprintln!("Writing block {}", lf_block.index);
 
-        file.seek(SeekFrom::Start(nif_position))?;
-        file.read_exact(&mut nif_buffer)?;
+        let nif_buffer = read_payload(
+            input,
+            format_args!("block {}", lf_block.index),
+            lf_block.file_offset,
+            lf_block.file_length,
+        )?;
 
-        let mut nif_file = File::create(out_dir_path.join(format!("{}.nif", lf_block.index)))?;
-        nif_file.write_all(&nif_buffer)?;
+        let nif_path = out_dir_path.join(format!("{}.nif", lf_block.index));
+        let mut nif_file = File::create(&nif_path).with_path(&nif_path)?;
+        nif_file.write_all(&nif_buffer).with_path(&nif_path)?;
     }
 
     Ok(())
@@ -186,67 +248,62 @@ struct PackOpts {
 }
 
 fn process_pack(pack_opts: PackOpts) -> anyhow::Result<()> {
-    let input_path = Path::new(&pack_opts.input_path);
-
-    let mut lf_archive: lf::Lf = {
-        let manifest_file = File::open(input_path)?;
-        serde_json::from_reader(manifest_file)?
-    };
+    pack_file(
+        Path::new(&pack_opts.input_path),
+        Path::new(&pack_opts.output_path),
+    )?;
 
-    lf_archive.header.version_date = 20090406;
-
-    let mut out_file = File::create(pack_opts.output_path)?;
-    out_file.write_all(b"LF\0\0kjc\0")?;
-    out_file.write_all(&lf_archive.header.unknown1.to_le_bytes())?;
-    out_file.write_all(&lf_archive.header.version_date.to_le_bytes())?;
-    out_file.write_all(&lf_archive.header.unknown2.to_le_bytes())?;
-    out_file.write_all(&lf_archive.header.block_count.to_le_bytes())?;
-    for unk3 in lf_archive.header.unknown3 {
-        out_file.write_all(&unk3.to_le_bytes())?;
-    }
-    out_file.write_all(&lf_archive.header.size_x.to_le_bytes())?;
-    out_file.write_all(&lf_archive.header.size_y.to_le_bytes())?;
-    out_file.write_all(&lf_archive.header.size_idx.to_le_bytes())?;
-    for unk4 in lf_archive.header.unknown4 {
-        out_file.write_all(&unk4.to_le_bytes())?;
-    }
+    Ok(())
+}
 
-    let mut offsets_offsets: Vec<u64> = Vec::new();
+/// Packs the manifest at `input_path` and the nifs next to it.
+pub fn pack_file(input_path: &Path, output_path: &Path) -> Result<()> {
+    let mut lf_archive: lf::Lf = read_json(input_path)?;
 
-    for block in lf_archive.blocks.iter() {
-        out_file.write_all(&block.index.to_le_bytes())?;
-        out_file.write_all(&block.position_x.to_le_bytes())?;
-        out_file.write_all(&block.position_y.to_le_bytes())?;
+    lf_archive.header.version_date = 20090406;
 
-        // Save position to fill in offsets later
-        offsets_offsets.push(out_file.seek(SeekFrom::Current(0))?);
-        out_file.write_all(&0_u32.to_le_bytes())?;
-        out_file.write_all(&0_u32.to_le_bytes())?;
+    let out_file = File::create(output_path).with_path(output_path)?;
+    pack(&lf_archive, &input_path.with_file_name(""), out_file)
+}
 
-        out_file.write_all(&block.unknown.to_le_bytes())?;
+/// Writes a terrain file, block nifs are read from `<index>.nif` in
+/// `nif_dir_path`.
+pub fn pack<W: Write>(lf_archive: &lf::Lf, nif_dir_path: &Path, mut out_file: W) -> Result<()> {
+    let block_file_paths: Vec<PathBuf> = lf_archive
+        .blocks
+        .iter()
+        .map(|block| nif_dir_path.join(format!("{}.nif", block.index)))
+        .collect();
+
+    // Nifs follow the block table in order
+    let mut file_offset = lf_archive.encoded_len()? as u32;
+    let mut blocks = Vec::with_capacity(lf_archive.blocks.len());
+    for (block, block_file_path) in lf_archive.blocks.iter().zip(block_file_paths.iter()) {
+        let file_length = std::fs::metadata(block_file_path)
+            .with_path(block_file_path)?
+            .len() as u32;
+        blocks.push(lf::Block {
+            file_offset,
+            file_length,
+            ..*block
+        });
+        file_offset += file_length;
     }
 
-    for (block, &header_offset) in lf_archive.blocks.iter().zip(offsets_offsets.iter()) {
-        let block_file_path = input_path.with_file_name(format!("{}.nif", block.index));
-
-        let file_offset = out_file.seek(SeekFrom::Current(0))? as u32;
-
-        let mut block_file = File::open(block_file_path)?;
-        let file_length = std::io::copy(&mut block_file, &mut out_file)? as u32;
+    lf_archive.header.encode(&mut out_file)?;
+    blocks.encode(&mut out_file)?;
 
-        // Go back and fill in header offsets
-        out_file.seek(SeekFrom::Start(header_offset))?;
-        out_file.write_all(&file_offset.to_le_bytes())?;
-        out_file.write_all(&file_length.to_le_bytes())?;
-        out_file.seek(SeekFrom::Start((file_offset + file_length).into()))?;
+    for block_file_path in block_file_paths.iter() {
+        let mut block_file = File::open(block_file_path).with_path(block_file_path)?;
+        std::io::copy(&mut block_file, &mut out_file)?;
     }
 
     Ok(())
 }
 
-pub fn process_lf(lf_opts: LfOpts) -> anyhow::Result<()> {
+pub fn process_lf(lf_opts: LfOpts, format: OutputFormat) -> anyhow::Result<()> {
     match lf_opts.cmd {
-        Command::Info(info_opts) => process_info(info_opts),
+        Command::Info(info_opts) => process_info(info_opts, format),
         Command::Unpack(unpack_opts) => process_unpack(unpack_opts),
         Command::Pack(pack_opts) => process_pack(pack_opts),
         Command::Obj(obj_opts) => process_obj(obj_opts),
@@ -0,0 +1,18 @@
+//! Readers, writers and command implementations for Drift City / Skid Rush
+//! files. The `slidetown-cli` binary is a thin front end over this crate.
+
+pub mod agt;
+pub mod encode;
+pub mod error;
+pub mod lbf;
+pub mod levelmodifier;
+pub mod lf;
+pub mod lof;
+pub mod loi;
+pub mod math;
+pub mod output;
+pub mod payload;
+pub mod world;
+
+pub use encode::Encode;
+pub use error::{Error, Result};
@@ -1,14 +1,21 @@
 use std::{
-    convert::TryInto,
+    collections::{BTreeMap, HashMap, HashSet},
     fs::File,
-    io::{Cursor, Read, Seek, SeekFrom, Write},
-    path::Path,
+    io::{Cursor, Read, Seek, Write},
+    path::{Path, PathBuf},
 };
 
 use clap::Clap;
-use encoding_rs::EUC_KR;
+use serde::{Deserialize, Serialize};
 use slidetown::parsers::lof;
 
+use crate::{
+    error::{parse_file, read_json, PathContext, Result},
+    output::{print_json, relative_output_path, OutputFormat},
+    payload::read_payload,
+    Encode,
+};
+
 #[derive(Clap)]
 pub struct LofOpts {
     #[clap(subcommand, about = "subcommand to run")]
@@ -36,15 +43,93 @@ struct InfoOpts {
     input_path: String,
 }
 
-fn process_info(info_opts: InfoOpts) -> anyhow::Result<()> {
-    let mut file = File::open(&info_opts.input_path)?;
-    let header: lof::Header = lof::Header::parse(&mut file)?;
+/// `lof info --format json` output.
+#[derive(Serialize)]
+struct Info {
+    /// Model count stored in the header
+    model_count: u32,
+}
+
+fn process_info(info_opts: InfoOpts, format: OutputFormat) -> anyhow::Result<()> {
+    let (header, _) = parse_file(Path::new(&info_opts.input_path), lof::Header::parse)?;
 
-    println!("Model count: {}", header.model_count);
+    let info = Info {
+        model_count: header.model_count,
+    };
+
+    match format {
+        OutputFormat::Json => print_json(&info)?,
+        OutputFormat::Text => println!("Model count: {}", info.model_count),
+    }
 
     Ok(())
 }
 
+/// Unpacked model table manifest. `file_paths` maps a model index to the path
+/// its nif was written to, relative to the manifest, whenever that differs from
+/// the model's `file_name`. Pack restores the original names from the table.
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(flatten)]
+    pub lof: lof::Lof,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub file_paths: BTreeMap<u32, String>,
+}
+
+/// Reads the model table out of an unpacked manifest.
+pub fn read_manifest(path: &Path) -> Result<lof::Lof> {
+    let manifest: Manifest = read_json(path)?;
+    Ok(manifest.lof)
+}
+
+/// Turns a model file name from the table into a relative path that stays
+/// inside the output directory, see `relative_output_path`.
+fn sanitize_file_name(file_name: &str, model_index: u32) -> PathBuf {
+    let mut path = relative_output_path(file_name);
+
+    if path.as_os_str().is_empty() {
+        path.push(format!("model{}.nif", model_index));
+    }
+
+    path
+}
+
+/// Appends `_N` to the file stem until the path no longer collides with one
+/// already taken. Comparison ignores case so the result also unpacks cleanly
+/// on case-insensitive filesystems.
+fn unique_path(path: PathBuf, taken: &mut HashSet<String>) -> PathBuf {
+    let key = |p: &Path| p.to_string_lossy().to_lowercase();
+
+    if taken.insert(key(&path)) {
+        return path;
+    }
+
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut counter = 1;
+    loop {
+        let candidate = path.with_file_name(format!("{}_{}{}", stem, counter, extension));
+        if taken.insert(key(&candidate)) {
+            return candidate;
+        }
+        counter += 1;
+    }
+}
+
+fn path_to_manifest_string(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
 #[derive(Clap)]
 struct UnpackOpts {
     #[clap(short, long, about = "input file")]
@@ -54,40 +139,76 @@ struct UnpackOpts {
 }
 
 fn process_unpack(unpack_opts: UnpackOpts) -> anyhow::Result<()> {
-    let mut file = File::open(&unpack_opts.input_path)?;
-
-    let lof_archive: lof::Lof = lof::Lof::parse(&mut file).expect("Could not parse LOF");
+    unpack_file(
+        Path::new(&unpack_opts.input_path),
+        Path::new(&unpack_opts.output_path),
+    )?;
 
-    let out_dir_path = Path::new(&unpack_opts.output_path);
-    std::fs::create_dir_all(out_dir_path).expect("Could not create output directory");
+    Ok(())
+}
 
-    {
-        let manifest_file = File::create(out_dir_path.join("manifest.json"))?;
-        serde_json::to_writer_pretty(manifest_file, &lof_archive)?;
-    }
+/// Parses the model table at `input_path` and unpacks it into `out_dir_path`.
+pub fn unpack_file(input_path: &Path, out_dir_path: &Path) -> Result<()> {
+    let (lof_archive, mut file) = parse_file(input_path, lof::Lof::parse)?;
 
-    for lof_model in lof_archive.models {
-        println!("Writing model {}", lof_model.file_name);
+    unpack(lof_archive, &mut file, out_dir_path).map_err(|e| e.in_file(input_path))
+}
 
-        let nif_position: u64 = lof_model.file_offset.into();
-        let nif_length: usize = lof_model
-            .file_length
-            .try_into()
-            .expect("Model file size too high");
+/// Writes every model nif and `manifest.json` into `out_dir_path`, reading
+/// the nifs from `input`.
+pub fn unpack<R: Read + Seek>(
+    lof_archive: lof::Lof,
+    input: &mut R,
+    out_dir_path: &Path,
+) -> Result<()> {
+    std::fs::create_dir_all(out_dir_path).with_path(out_dir_path)?;
+
+    let mut taken_paths = HashSet::new();
+    let mut file_paths = BTreeMap::new();
+    let mut model_paths = Vec::new();
+
+    for lof_model in lof_archive.models.iter() {
+        let sanitized = sanitize_file_name(&lof_model.file_name, lof_model.index);
+        let model_path = unique_path(sanitized, &mut taken_paths);
+
+        let model_path_string = path_to_manifest_string(&model_path);
+        if model_path_string != lof_model.file_name {
+            eprintln!(
+                "Model {} file name {:?} unpacked as {:?}",
+                lof_model.index, lof_model.file_name, model_path_string
+            );
+            file_paths.insert(lof_model.index, model_path_string);
+        }
+
+        model_paths.push(model_path);
+    }
 
-        let mut nif_buffer = vec![0u8; nif_length];
+    for (lof_model, model_path) in lof_archive.models.iter().zip(model_paths) {
+        eprintln!("Writing model {}", lof_model.file_name);
 
-        file.seek(SeekFrom::Start(nif_position))?;
-        file.read_exact(&mut nif_buffer)?;
+        let nif_buffer = read_payload(
+            input,
+            format_args!("model {}", lof_model.index),
+            lof_model.file_offset,
+            lof_model.file_length,
+        )?;
 
-        let nif_path = out_dir_path.join(lof_model.file_name);
+        let nif_path = out_dir_path.join(model_path);
         let nif_dir = nif_path.with_file_name("");
-        std::fs::create_dir_all(nif_dir).expect("Could not create directory for model");
+        std::fs::create_dir_all(&nif_dir).with_path(&nif_dir)?;
 
-        let mut nif_file = File::create(nif_path).expect("Failed to open model file for writing");
-        nif_file
-            .write_all(&nif_buffer)
-            .expect("Failed to write to model file");
+        let mut nif_file = File::create(&nif_path).with_path(&nif_path)?;
+        nif_file.write_all(&nif_buffer).with_path(&nif_path)?;
+    }
+
+    {
+        let manifest = Manifest {
+            lof: lof_archive,
+            file_paths,
+        };
+        let manifest_path = out_dir_path.join("manifest.json");
+        let manifest_file = File::create(&manifest_path).with_path(&manifest_path)?;
+        serde_json::to_writer_pretty(manifest_file, &manifest)?;
     }
 
     Ok(())
@@ -102,64 +223,63 @@ struct PackOpts {
 }
 
 fn process_pack(pack_opts: PackOpts) -> anyhow::Result<()> {
-    let input_path = Path::new(&pack_opts.input_path);
+    pack_file(
+        Path::new(&pack_opts.input_path),
+        Path::new(&pack_opts.output_path),
+    )?;
 
-    let lof_archive: lof::Lof = {
-        let manifest_file = File::open(input_path).expect("Failed to open manifest for reading");
-        serde_json::from_reader(manifest_file).expect("Failed to parse manifest")
-    };
+    Ok(())
+}
 
-    let mut out_file =
-        File::create(pack_opts.output_path).expect("Failed to create lof for writing");
-    out_file.write_all(b"LOF\0kjc\0")?;
-    out_file.write_all(&lof_archive.header.unknown1.to_le_bytes())?;
-    out_file.write_all(&lof_archive.header.version_date.to_le_bytes())?;
-    out_file.write_all(&lof_archive.header.model_count.to_le_bytes())?;
-    out_file.write_all(&lof_archive.header.unknown2.to_le_bytes())?;
-
-    let mut offsets_offsets: Vec<u64> = Vec::new();
-
-    for model in lof_archive.models.iter() {
-        out_file.write_all(&model.index.to_le_bytes())?;
-        out_file.write_all(&model.unknown1.to_le_bytes())?;
-        out_file.write_all(&model.unknown2.to_le_bytes())?;
-        out_file.write_all(&model.unknown3.to_le_bytes())?;
-        out_file.write_all(&model.unknown4.to_le_bytes())?;
-        out_file.write_all(&model.unknown5.to_le_bytes())?;
-
-        let (encoded_name, _used_encoding, _had_errors) = EUC_KR.encode(&model.name);
-        out_file.write_all(&encoded_name)?;
-        out_file.write_all(&[0u8; 1])?;
-
-        let (encoded_file_name, _used_encoding, _had_errors) = EUC_KR.encode(&model.file_name);
-        out_file.write_all(&encoded_file_name)?;
-        out_file.write_all(&[0u8; 1])?;
-
-        out_file.write_all(&model.unknown6.to_le_bytes())?;
-        out_file.write_all(&model.unknown7.to_le_bytes())?;
-        out_file.write_all(&model.unknown8.to_le_bytes())?;
-
-        // Save position to fill in offsets later
-        offsets_offsets.push(out_file.seek(SeekFrom::Current(0))?);
-        out_file.write_all(&0_u32.to_le_bytes())?;
-        out_file.write_all(&0_u32.to_le_bytes())?;
-    }
+/// Packs the manifest at `input_path` and the nifs next to it.
+pub fn pack_file(input_path: &Path, output_path: &Path) -> Result<()> {
+    let manifest: Manifest = read_json(input_path)?;
 
-    for (model, &header_offset) in lof_archive.models.iter().zip(offsets_offsets.iter()) {
-        let model_file_path = input_path.with_file_name("").join(&model.file_name);
+    let out_file = File::create(output_path).with_path(output_path)?;
+    pack(&manifest, &input_path.with_file_name(""), out_file)
+}
 
-        let file_offset = out_file.seek(SeekFrom::Current(0))? as u32;
+/// Writes a model table, model nifs are read relative to `nif_dir_path`.
+pub fn pack<W: Write>(manifest: &Manifest, nif_dir_path: &Path, mut out_file: W) -> Result<()> {
+    let lof_archive = &manifest.lof;
+
+    // manifest paths may have been edited by hand, so they get the same
+    // treatment as the names in the table and can't leave `nif_dir_path`
+    let model_file_paths: Vec<PathBuf> = lof_archive
+        .models
+        .iter()
+        .map(|model| {
+            let file_name = manifest
+                .file_paths
+                .get(&model.index)
+                .unwrap_or(&model.file_name);
+            nif_dir_path.join(sanitize_file_name(file_name, model.index))
+        })
+        .collect();
+
+    // Nifs follow the model table in order
+    let mut file_offset = lof_archive.encoded_len()? as u32;
+    let mut models = Vec::with_capacity(lof_archive.models.len());
+    for (model, model_file_path) in lof_archive.models.iter().zip(model_file_paths.iter()) {
+        let file_length = std::fs::metadata(model_file_path)
+            .with_path(model_file_path)?
+            .len() as u32;
+        models.push(lof::Model {
+            name: model.name.clone(),
+            file_name: model.file_name.clone(),
+            file_offset,
+            file_length,
+            ..*model
+        });
+        file_offset += file_length;
+    }
 
-        let mut model_file =
-            File::open(model_file_path).expect("Failed to open model for writing into lof");
-        let file_length = std::io::copy(&mut model_file, &mut out_file)
-            .expect("Failed to write model to lof") as u32;
+    lof_archive.header.encode(&mut out_file)?;
+    models.encode(&mut out_file)?;
 
-        // Go back and fill in header offsets
-        out_file.seek(SeekFrom::Start(header_offset))?;
-        out_file.write_all(&file_offset.to_le_bytes())?;
-        out_file.write_all(&file_length.to_le_bytes())?;
-        out_file.seek(SeekFrom::Start((file_offset + file_length).into()))?;
+    for model_file_path in model_file_paths.iter() {
+        let mut model_file = File::open(model_file_path).with_path(model_file_path)?;
+        std::io::copy(&mut model_file, &mut out_file)?;
     }
 
     Ok(())
@@ -173,31 +293,46 @@ struct GltfOpts {
     output_path: String,
 }
 
-pub fn process_gltf_inner(
-    input_path: &str,
-    scene_name: Option<&str>,
-) -> anyhow::Result<(
-    nif::gltf::Gltf,
-    std::collections::HashMap<u32, nif::gltf::json::Index<nif::gltf::json::Node>>,
-)> {
-    let mut file = File::open(&input_path)?;
-    let lof: lof::Lof = lof::Lof::parse(&mut file)?;
+/// Gltf node of each model by model index.
+pub type ModelNodes = HashMap<u32, nif::gltf::json::Index<nif::gltf::json::Node>>;
 
+/// Builds a new gltf with every model in the table at `input_path`.
+pub fn models_to_gltf(
+    input_path: &Path,
+    scene_name: Option<&str>,
+) -> Result<(nif::gltf::Gltf, ModelNodes)> {
     let mut gltf = nif::gltf::Gltf::new();
-    let mut model_indices = std::collections::HashMap::new();
+    let model_indices = visit_gltf_models(&mut gltf, input_path, scene_name)?;
 
-    for model in lof.models {
-        file.seek(SeekFrom::Start(model.file_offset as u64))?;
+    Ok((gltf, model_indices))
+}
+
+/// Adds every model in the table to an existing gltf, returning the node of
+/// each model by model index. Models whose nif fails to parse are skipped.
+pub fn visit_gltf_models(
+    gltf: &mut nif::gltf::Gltf,
+    input_path: &Path,
+    scene_name: Option<&str>,
+) -> Result<ModelNodes> {
+    let (lof, mut file) = parse_file(input_path, lof::Lof::parse)?;
 
-        let mut nif_buf = vec![0u8; model.file_length as usize];
-        file.read_exact(&mut nif_buf)?;
+    let mut model_indices = HashMap::new();
+
+    for model in lof.models {
+        let nif_buf = read_payload(
+            &mut file,
+            format_args!("model {}", model.index),
+            model.file_offset,
+            model.file_length,
+        )
+        .map_err(|e| e.in_file(input_path))?;
 
         let mut nif_cursor = Cursor::new(nif_buf);
 
         let nif = match nif::Nif::parse(&mut nif_cursor) {
             Ok(nif) => nif,
             Err(e) => {
-                println!(
+                eprintln!(
                     "Failed to parse NIF for model index {}: {:?}",
                     model.index, e
                 );
@@ -211,11 +346,11 @@ pub fn process_gltf_inner(
         );
     }
 
-    Ok((gltf, model_indices))
+    Ok(model_indices)
 }
 
 fn process_gltf(gltf_opts: GltfOpts) -> anyhow::Result<()> {
-    let (gltf, _model_indices) = process_gltf_inner(&gltf_opts.input_path, Some("Models"))?;
+    let (gltf, _model_indices) = models_to_gltf(Path::new(&gltf_opts.input_path), Some("Models"))?;
 
     let gltf_path = std::path::PathBuf::from(gltf_opts.output_path);
     gltf.write_to_files(gltf_path)?;
@@ -223,9 +358,9 @@ fn process_gltf(gltf_opts: GltfOpts) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub fn process_lof(lof_opts: LofOpts) -> anyhow::Result<()> {
+pub fn process_lof(lof_opts: LofOpts, format: OutputFormat) -> anyhow::Result<()> {
     match lof_opts.cmd {
-        Command::Info(info_opts) => process_info(info_opts),
+        Command::Info(info_opts) => process_info(info_opts, format),
         Command::Unpack(unpack_opts) => process_unpack(unpack_opts),
         Command::Pack(pack_opts) => process_pack(pack_opts),
         Command::Gltf(gltf_opts) => process_gltf(gltf_opts),
@@ -1,8 +1,27 @@
-use std::{fs::File, io::Write, path::Path};
+use std::{collections::HashMap, fs::File, path::Path};
 
 use clap::Clap;
+use serde::{Deserialize, Serialize};
 use slidetown::parsers::loi;
 
+use crate::{
+    error::{parse_file, read_json, Error, PathContext, Result},
+    output::{is_json_path, print_json, OutputFormat},
+    Encode,
+};
+
+mod find;
+mod gltf;
+mod grid;
+mod objects;
+mod placements;
+pub mod selection;
+mod transform;
+mod unknowns;
+mod validate;
+
+pub use self::gltf::tag_instance_nodes;
+
 #[derive(Clap)]
 pub struct LoiOpts {
     #[clap(subcommand, about = "subcommand to run")]
@@ -19,6 +38,32 @@ enum Command {
     Pack(PackOpts),
     #[clap(about = "export preview gltf with instanced objects")]
     Gltf(GltfOpts),
+    #[clap(
+        name = "import-gltf",
+        about = "apply instance edits from a gltf exported by the gltf subcommand"
+    )]
+    ImportGltf(gltf::ImportGltfOpts),
+    #[clap(name = "export-csv", about = "export object placements as csv/tsv")]
+    ExportCsv(placements::ExportCsvOpts),
+    #[clap(name = "import-csv", about = "import object placements from csv/tsv")]
+    ImportCsv(placements::ImportCsvOpts),
+    #[clap(about = "reassign objects to blocks based on their position")]
+    Rebucket(RebucketOpts),
+    #[clap(about = "check counts and cross-references in object list")]
+    Validate(validate::ValidateOpts),
+    #[clap(about = "search placed objects by model, area or block")]
+    Find(find::FindOpts),
+    #[clap(about = "translate, rotate, scale or mirror selected objects")]
+    Transform(transform::TransformOpts),
+    #[clap(
+        name = "analyze-unknowns",
+        about = "correlate unknown trailing sections with objects and blocks"
+    )]
+    AnalyzeUnknowns(unknowns::AnalyzeUnknownsOpts),
+    #[clap(name = "add-object", about = "place a new object")]
+    AddObject(objects::AddObjectOpts),
+    #[clap(name = "remove-object", about = "remove placed objects")]
+    RemoveObject(objects::RemoveObjectOpts),
 }
 
 #[derive(Clap)]
@@ -27,25 +72,21 @@ struct InfoOpts {
     input_path: String,
 }
 
-fn process_info(info_opts: InfoOpts) -> anyhow::Result<()> {
-    let mut file = File::open(&info_opts.input_path)?;
-    let loi: loi::Loi = loi::Loi::parse(&mut file)?;
+/// `loi info --format json` output.
+#[derive(Serialize)]
+struct Info {
+    /// Block count stored in the header
+    block_count: u32,
+    /// Blocks with at least one object
+    blocks_with_objects: usize,
+    /// Sum of the object counts of all blocks
+    object_count: u32,
+    /// Highest object_index, 0 when there are no objects
+    max_object_index: u32,
+}
 
-    println!("Block count: {}", loi.header.block_count);
-    println!(
-        "Blocks with any objects in them {}",
-        loi.blocks
-            .iter()
-            .filter(|block| block.object_count > 0)
-            .count()
-    );
-    println!(
-        "Object count sum over blocks {}",
-        loi.blocks
-            .iter()
-            .map(|block| block.object_count)
-            .sum::<u32>()
-    );
+fn process_info(info_opts: InfoOpts, format: OutputFormat) -> anyhow::Result<()> {
+    let (loi, _) = parse_file(Path::new(&info_opts.input_path), loi::Loi::parse)?;
 
     let object_indices = loi
         .blocks
@@ -55,7 +96,33 @@ fn process_info(info_opts: InfoOpts) -> anyhow::Result<()> {
 
     let object_indices_max = *object_indices.iter().max().unwrap_or(&0);
 
-    println!("Highest object_index {}", object_indices_max);
+    let info = Info {
+        block_count: loi.header.block_count,
+        blocks_with_objects: loi
+            .blocks
+            .iter()
+            .filter(|block| block.object_count > 0)
+            .count(),
+        object_count: loi
+            .blocks
+            .iter()
+            .map(|block| block.object_count)
+            .sum::<u32>(),
+        max_object_index: object_indices_max,
+    };
+
+    match format {
+        OutputFormat::Json => print_json(&info)?,
+        OutputFormat::Text => {
+            println!("Block count: {}", info.block_count);
+            println!(
+                "Blocks with any objects in them {}",
+                info.blocks_with_objects
+            );
+            println!("Object count sum over blocks {}", info.object_count);
+            println!("Highest object_index {}", info.max_object_index);
+        }
+    }
 
     // println!("Blocks with objects:");
     // for block in loi.blocks.iter() {
@@ -92,22 +159,32 @@ struct UnpackOpts {
     input_path: String,
     #[clap(short, long, about = "output file")]
     output_path: String,
+    #[clap(long, about = "write decoded views of known trailing sections")]
+    decode: bool,
 }
 
 fn process_unpack(unpack_opts: UnpackOpts) -> anyhow::Result<()> {
-    let mut file = File::open(&unpack_opts.input_path).expect("Failed to open source file");
+    unpack_file(
+        Path::new(&unpack_opts.input_path),
+        Path::new(&unpack_opts.output_path),
+        unpack_opts.decode,
+    )?;
 
-    let loi_archive: loi::Loi = loi::Loi::parse(&mut file).expect("Failed to parse source file");
+    Ok(())
+}
 
-    let out_path = Path::new(&unpack_opts.output_path);
+/// Parses the object list at `input_path` and writes it as a JSON manifest,
+/// with the known trailing sections decoded if `decode` is set.
+pub fn unpack_file(input_path: &Path, output_path: &Path, decode: bool) -> Result<()> {
+    let (loi_archive, _) = parse_file(input_path, loi::Loi::parse)?;
 
-    {
-        let json_file = File::create(out_path).expect("Failed to open target file");
+    let json_file = File::create(output_path).with_path(output_path)?;
+    if decode {
+        serde_json::to_writer_pretty(json_file, &Manifest::decoded(loi_archive))
+    } else {
         serde_json::to_writer_pretty(json_file, &loi_archive)
-            .expect("Failed to write to target file");
     }
-
-    Ok(())
+    .map_err(|e| Error::from(e).in_file(output_path))
 }
 
 #[derive(Clap)]
@@ -121,110 +198,81 @@ struct PackOpts {
 fn process_pack(pack_opts: PackOpts) -> anyhow::Result<()> {
     let input_path = Path::new(&pack_opts.input_path);
 
-    let loi: loi::Loi = {
-        let manifest_file = File::open(input_path)?;
-        serde_json::from_reader(manifest_file)?
-    };
+    let loi = read_json::<Manifest>(input_path)?.into_loi()?;
+    write_loi_file(Path::new(&pack_opts.output_path), &loi)?;
 
-    let mut out_file = File::create(pack_opts.output_path)?;
-    out_file.write_all(b"LOI\0kjc\0")?;
-    out_file.write_all(&loi.header.unknown1.to_le_bytes())?;
-    out_file.write_all(&loi.header.version_date.to_le_bytes())?;
-    out_file.write_all(&loi.header.block_count.to_le_bytes())?;
+    Ok(())
+}
 
-    for block in loi.blocks.iter() {
-        out_file.write_all(&block.block_index.to_le_bytes())?;
-        out_file.write_all(&block.object_count.to_le_bytes())?;
+/// Unpacked object list. Sections that have been decoded can be moved into
+/// their own views, which take the place of the raw section and have their
+/// counts recomputed on pack.
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(flatten)]
+    pub loi: loi::Loi,
+    /// `unknown_objects_5` as plain lists of object indices
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_groups: Option<Vec<Vec<u32>>>,
+}
 
-        for block_object in block.objects.iter() {
-            out_file.write_all(&block_object.unknown1.to_le_bytes())?;
-            out_file.write_all(&block_object.unknown2.to_le_bytes())?;
-            out_file.write_all(&block_object.unknown3.to_le_bytes())?;
-            out_file.write_all(&block_object.unknown4.to_le_bytes())?;
-            out_file.write_all(&block_object.object_index.to_le_bytes())?;
-            out_file.write_all(&block_object.block_index.to_le_bytes())?;
-            out_file.write_all(&block_object.model_table_index.to_le_bytes())?;
-            out_file.write_all(&block_object.position.0.to_le_bytes())?;
-            out_file.write_all(&block_object.position.1.to_le_bytes())?;
-            out_file.write_all(&block_object.position.2.to_le_bytes())?;
-            out_file.write_all(&block_object.rotation.0 .0.to_le_bytes())?;
-            out_file.write_all(&block_object.rotation.0 .1.to_le_bytes())?;
-            out_file.write_all(&block_object.rotation.0 .2.to_le_bytes())?;
-            out_file.write_all(&block_object.rotation.1 .0.to_le_bytes())?;
-            out_file.write_all(&block_object.rotation.1 .1.to_le_bytes())?;
-            out_file.write_all(&block_object.rotation.1 .2.to_le_bytes())?;
-            out_file.write_all(&block_object.rotation.2 .0.to_le_bytes())?;
-            out_file.write_all(&block_object.rotation.2 .1.to_le_bytes())?;
-            out_file.write_all(&block_object.rotation.2 .2.to_le_bytes())?;
-            out_file.write_all(&block_object.scale.to_le_bytes())?;
-            out_file.write_all(&block_object.unknown8.to_le_bytes())?;
-            out_file.write_all(&block_object.unknown9.to_le_bytes())?;
-            out_file.write_all(&block_object.object_extra_index.to_le_bytes())?;
-            out_file.write_all(&block_object.unknown11.to_le_bytes())?;
+impl Manifest {
+    pub fn decoded(mut loi: loi::Loi) -> Self {
+        let object_groups = loi
+            .unknown_objects_5
+            .drain(..)
+            .map(|unknown_object_5| unknown_object_5.object_indices)
+            .collect();
+
+        Manifest {
+            loi,
+            object_groups: Some(object_groups),
         }
     }
 
-    out_file.write_all(&loi.object_extra_count.to_le_bytes())?;
-
-    for object_extra in loi.object_extras.iter() {
-        out_file.write_all(&object_extra.object_index.to_le_bytes())?;
-        out_file.write_all(&object_extra.object_extra_index.to_le_bytes())?;
-        out_file.write_all(&object_extra.unknown3.to_le_bytes())?;
-        out_file.write_all(&object_extra.position.0.to_le_bytes())?;
-        out_file.write_all(&object_extra.position.1.to_le_bytes())?;
-        out_file.write_all(&object_extra.position.2.to_le_bytes())?;
-        out_file.write_all(&object_extra.rotation.0 .0.to_le_bytes())?;
-        out_file.write_all(&object_extra.rotation.0 .1.to_le_bytes())?;
-        out_file.write_all(&object_extra.rotation.0 .2.to_le_bytes())?;
-        out_file.write_all(&object_extra.rotation.1 .0.to_le_bytes())?;
-        out_file.write_all(&object_extra.rotation.1 .1.to_le_bytes())?;
-        out_file.write_all(&object_extra.rotation.1 .2.to_le_bytes())?;
-        out_file.write_all(&object_extra.rotation.2 .0.to_le_bytes())?;
-        out_file.write_all(&object_extra.rotation.2 .1.to_le_bytes())?;
-        out_file.write_all(&object_extra.rotation.2 .2.to_le_bytes())?;
-        out_file.write_all(&object_extra.unknown4.0.to_le_bytes())?;
-        out_file.write_all(&object_extra.unknown4.1.to_le_bytes())?;
-        out_file.write_all(&object_extra.unknown4.2.to_le_bytes())?;
-        out_file.write_all(&object_extra.unknown5.to_le_bytes())?;
-    }
-
-    for unknown_object_2 in loi.unknown_objects_2.iter() {
-        out_file.write_all(&unknown_object_2.unknown_count.to_le_bytes())?;
-
-        for unknown_object_2_item in unknown_object_2.items.iter() {
-            out_file.write_all(&unknown_object_2_item.to_le_bytes())?;
+    pub fn into_loi(self) -> Result<loi::Loi> {
+        let mut loi = self.loi;
+
+        if let Some(object_groups) = self.object_groups {
+            if !loi.unknown_objects_5.is_empty() {
+                return Err(Error::Inconsistent(vec![
+                    "manifest has both object_groups and unknown_objects_5".to_string(),
+                ]));
+            }
+            loi.unknown_objects_5 = object_groups
+                .into_iter()
+                .map(|object_indices| loi::UnknownObject5 {
+                    object_count: object_indices.len() as u32,
+                    object_indices,
+                })
+                .collect();
         }
-    }
-
-    out_file.write_all(&loi.unknown_object_3_count.to_le_bytes())?;
-
-    for unknown_object_3 in loi.unknown_objects_3.iter() {
-        out_file.write_all(&unknown_object_3.unknown1.to_le_bytes())?;
-        out_file.write_all(&unknown_object_3.unknown_count.to_le_bytes())?;
 
-        for unknown_object_3_item in unknown_object_3.items.iter() {
-            out_file.write_all(&unknown_object_3_item.to_le_bytes())?;
-        }
+        Ok(loi)
     }
+}
 
-    for unknown_object_4 in loi.unknown_objects_4.iter() {
-        out_file.write_all(&unknown_object_4.unknown_count.to_le_bytes())?;
-        out_file.write_all(&unknown_object_4.unknown1.to_le_bytes())?;
-
-        for unknown_object_4_item in unknown_object_4.items.iter() {
-            out_file.write_all(&unknown_object_4_item.to_le_bytes())?;
-        }
+/// Reads an object list from a JSON manifest if the path ends in `.json`,
+/// otherwise from a packed LOI.
+pub fn read_loi(path: &Path) -> Result<loi::Loi> {
+    if is_json_path(path) {
+        read_json::<Manifest>(path)?.into_loi()
+    } else {
+        Ok(parse_file(path, loi::Loi::parse)?.0)
     }
+}
 
-    for unknown_object_5 in loi.unknown_objects_5.iter() {
-        out_file.write_all(&unknown_object_5.object_count.to_le_bytes())?;
+/// Writes an object list as a JSON manifest if the path ends in `.json`,
+/// otherwise packs it.
+pub fn write_loi_file(path: &Path, loi: &loi::Loi) -> Result<()> {
+    let mut file = File::create(path).with_path(path)?;
 
-        for object_index in unknown_object_5.object_indices.iter() {
-            out_file.write_all(&object_index.to_le_bytes())?;
-        }
+    if is_json_path(path) {
+        serde_json::to_writer_pretty(file, loi).map_err(Error::from)
+    } else {
+        loi.encode(&mut file).map_err(Error::from)
     }
-
-    Ok(())
+    .map_err(|e| e.in_file(path))
 }
 
 #[derive(Clap)]
@@ -238,21 +286,34 @@ struct GltfOpts {
     output_path: String,
 }
 
-fn process_gltf(gltf_opts: GltfOpts) -> anyhow::Result<()> {
-    let mut file = File::open(&gltf_opts.loi_path)?;
-    let loi: loi::Loi = loi::Loi::parse(&mut file)?;
-
-    let (mut gltf, model_indices) =
-        crate::lof::process_gltf_inner(&gltf_opts.lof_path, None).expect("failed to process lof");
-
+/// Instances every object in the blocks accepted by the filter into the
+/// "Instanced Objects" scene, returning the tags to write with
+/// `tag_instance_nodes` once the gltf is written.
+pub fn visit_gltf_instances<F>(
+    gltf: &mut nif::gltf::Gltf,
+    loi: &loi::Loi,
+    model_indices: &crate::lof::ModelNodes,
+    block_filter: F,
+) -> Vec<gltf::InstanceTag>
+where
+    F: Fn(&loi::Block) -> bool,
+{
     let mut instance_indices = Vec::new();
+    let mut instance_tags = Vec::new();
 
-    for block in loi.blocks {
-        for block_object in block.objects {
-            let &model_node_index = model_indices
-                .get(&block_object.model_table_index)
-                .expect("couldn't find model");
-            instance_indices.push(gltf.clone_node(
+    for block in loi.blocks.iter().filter(|block| block_filter(block)) {
+        for block_object in block.objects.iter() {
+            let model_node_index = match model_indices.get(&block_object.model_table_index) {
+                Some(&model_node_index) => model_node_index,
+                None => {
+                    eprintln!(
+                        "Model {} not found for object index {}, skipping",
+                        block_object.model_table_index, block_object.object_index
+                    );
+                    continue;
+                }
+            };
+            let instance_index = gltf.clone_node(
                 model_node_index,
                 Some([
                     block_object.position.0,
@@ -271,23 +332,149 @@ fn process_gltf(gltf_opts: GltfOpts) -> anyhow::Result<()> {
                     block_object.rotation.2 .2,
                 ]),
                 Some(block_object.scale),
-            ));
+            );
+
+            instance_tags.push(gltf::InstanceTag {
+                node_index: instance_index.value(),
+                object_index: block_object.object_index,
+                model_table_index: block_object.model_table_index,
+                block_index: block.block_index,
+            });
+            instance_indices.push(instance_index);
         }
     }
 
-    gltf.get_or_create_scene("Instanced Objects", Some(instance_indices));
+    gltf.get_or_create_scene(gltf::INSTANCE_SCENE, Some(instance_indices));
+
+    instance_tags
+}
+
+fn process_gltf(gltf_opts: GltfOpts) -> anyhow::Result<()> {
+    let (loi, _) = parse_file(Path::new(&gltf_opts.loi_path), loi::Loi::parse)?;
+
+    let (mut gltf, model_indices) =
+        crate::lof::models_to_gltf(Path::new(&gltf_opts.lof_path), None)?;
+
+    let instance_tags = visit_gltf_instances(&mut gltf, &loi, &model_indices, |_| true);
 
     let gltf_path = std::path::PathBuf::from(gltf_opts.output_path);
-    gltf.write_to_files(gltf_path)?;
+    gltf.write_to_files(gltf_path.clone())?;
+    tag_instance_nodes(&gltf_path, &instance_tags)?;
+
+    Ok(())
+}
+
+#[derive(Clap)]
+struct RebucketOpts {
+    #[clap(short, long, about = "input file (loi or json manifest)")]
+    input_path: String,
+    #[clap(long, about = "path to terrain0.lf")]
+    lf: String,
+    #[clap(
+        long,
+        about = "block size in world units, derived from the LF if omitted"
+    )]
+    block_size: Option<f32>,
+    #[clap(short, long, about = "output file (loi or json manifest)")]
+    output_path: String,
+}
+
+fn process_rebucket(rebucket_opts: RebucketOpts) -> anyhow::Result<()> {
+    let mut loi = read_loi(Path::new(&rebucket_opts.input_path))?;
+    let grid =
+        grid::BlockGrid::from_lf_path(Path::new(&rebucket_opts.lf), rebucket_opts.block_size)?;
+
+    let block_positions: HashMap<u32, usize> = loi
+        .blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| (block.block_index, i))
+        .collect();
+
+    let mut objects = Vec::new();
+    for (block_position, block) in loi.blocks.iter_mut().enumerate() {
+        objects.extend(
+            block
+                .objects
+                .drain(..)
+                .map(|object| (block_position, object)),
+        );
+    }
+
+    let mut moved_count = 0;
+    let mut outside_count = 0;
+    let mut missing_count = 0;
+
+    for (current_block_position, mut object) in objects {
+        let block_position = match grid.block_index(object.position) {
+            Some(block_index) => match block_positions.get(&block_index) {
+                Some(&block_position) => block_position,
+                None => {
+                    eprintln!(
+                        "Object {} at {:?} belongs in block {} which the loi doesn't have, keeping it in block {}",
+                        object.object_index,
+                        object.position,
+                        block_index,
+                        loi.blocks[current_block_position].block_index
+                    );
+                    missing_count += 1;
+                    current_block_position
+                }
+            },
+            None => {
+                eprintln!(
+                    "Object {} at {:?} is outside the map, keeping it in block {}",
+                    object.object_index,
+                    object.position,
+                    loi.blocks[current_block_position].block_index
+                );
+                outside_count += 1;
+                current_block_position
+            }
+        };
+
+        if block_position != current_block_position {
+            moved_count += 1;
+        }
+
+        let block = &mut loi.blocks[block_position];
+        object.block_index = block.block_index;
+        block.objects.push(object);
+    }
+
+    for block in loi.blocks.iter_mut() {
+        block.object_count = block.objects.len() as u32;
+    }
+
+    write_loi_file(Path::new(&rebucket_opts.output_path), &loi)?;
+
+    eprintln!(
+        "Moved {} objects between blocks, {} outside the map, {} in blocks missing from the loi",
+        moved_count, outside_count, missing_count
+    );
 
     Ok(())
 }
 
-pub fn process_loi(loi_opts: LoiOpts) -> anyhow::Result<()> {
+pub fn process_loi(loi_opts: LoiOpts, format: OutputFormat) -> anyhow::Result<()> {
     match loi_opts.cmd {
-        Command::Info(info_opts) => process_info(info_opts),
+        Command::Info(info_opts) => process_info(info_opts, format),
         Command::Unpack(unpack_opts) => process_unpack(unpack_opts),
         Command::Pack(pack_opts) => process_pack(pack_opts),
         Command::Gltf(gltf_opts) => process_gltf(gltf_opts),
+        Command::ImportGltf(import_gltf_opts) => gltf::process_import_gltf(import_gltf_opts),
+        Command::ExportCsv(export_csv_opts) => placements::process_export_csv(export_csv_opts),
+        Command::ImportCsv(import_csv_opts) => placements::process_import_csv(import_csv_opts),
+        Command::Rebucket(rebucket_opts) => process_rebucket(rebucket_opts),
+        Command::Validate(validate_opts) => validate::process_validate(validate_opts),
+        Command::Find(find_opts) => find::process_find(find_opts, format),
+        Command::Transform(transform_opts) => transform::process_transform(transform_opts),
+        Command::AnalyzeUnknowns(analyze_unknowns_opts) => {
+            unknowns::process_analyze_unknowns(analyze_unknowns_opts)
+        }
+        Command::AddObject(add_object_opts) => objects::process_add_object(add_object_opts),
+        Command::RemoveObject(remove_object_opts) => {
+            objects::process_remove_object(remove_object_opts)
+        }
     }
 }
@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use clap::Clap;
+use serde::Serialize;
+
+use super::selection::SelectionOpts;
+use crate::output::{print_json, OutputFormat};
+
+#[derive(Clap)]
+pub struct FindOpts {
+    #[clap(short, long, about = "input file (loi or json manifest)")]
+    input_path: String,
+    #[clap(flatten)]
+    selection: SelectionOpts,
+}
+
+#[derive(Serialize)]
+struct FoundObject<'a> {
+    block_index: u32,
+    object_index: u32,
+    model_table_index: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model_name: Option<&'a str>,
+    position: [f32; 3],
+    scale: f32,
+}
+
+pub fn process_find(find_opts: FindOpts, format: OutputFormat) -> anyhow::Result<()> {
+    let loi = super::read_loi(Path::new(&find_opts.input_path))?;
+    let selection = find_opts.selection.resolve()?;
+
+    let mut found = Vec::new();
+
+    for block in loi.blocks.iter() {
+        for object in block.objects.iter() {
+            if !selection.matches(block, object) {
+                continue;
+            }
+
+            found.push(FoundObject {
+                block_index: block.block_index,
+                object_index: object.object_index,
+                model_table_index: object.model_table_index,
+                model_name: selection.model_name(object.model_table_index),
+                position: [object.position.0, object.position.1, object.position.2],
+                scale: object.scale,
+            });
+        }
+    }
+
+    if format == OutputFormat::Json {
+        return print_json(&found);
+    }
+
+    for object in found.iter() {
+        let model = match object.model_name {
+            Some(model_name) => format!("{} ({})", object.model_table_index, model_name),
+            None => object.model_table_index.to_string(),
+        };
+        println!(
+            "Object {} in block {}, model {}, at ({}, {}, {}) scale {}",
+            object.object_index,
+            object.block_index,
+            model,
+            object.position[0],
+            object.position[1],
+            object.position[2],
+            object.scale
+        );
+    }
+    println!("Found {} objects", found.len());
+
+    Ok(())
+}
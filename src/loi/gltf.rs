@@ -0,0 +1,359 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fs::File,
+    io::Read,
+    path::Path,
+};
+
+use anyhow::Context;
+use clap::Clap;
+use serde_json::{json, Value};
+use slidetown::parsers::loi;
+
+use super::objects;
+use crate::{
+    error::{read_json, PathContext},
+    math,
+};
+
+/// Identifies the object an instance node was created from. Written into the
+/// node's extras, which Blender imports and exports as custom properties.
+pub struct InstanceTag {
+    pub node_index: usize,
+    pub object_index: u32,
+    pub model_table_index: u32,
+    pub block_index: u32,
+}
+
+/// Name of the scene the instance nodes are collected in.
+pub const INSTANCE_SCENE: &str = "Instanced Objects";
+
+/// Adds the instance tags to an already written gltf file. The instance
+/// scene's extras also list every exported object, so an import can tell
+/// deleted nodes apart from objects that were never exported.
+pub fn tag_instance_nodes(gltf_path: &Path, tags: &[InstanceTag]) -> anyhow::Result<()> {
+    let mut root: Value = read_json(gltf_path)?;
+
+    let scene = root
+        .get_mut("scenes")
+        .and_then(Value::as_array_mut)
+        .and_then(|scenes| {
+            scenes
+                .iter_mut()
+                .find(|scene| scene.get("name").and_then(Value::as_str) == Some(INSTANCE_SCENE))
+        })
+        .with_context(|| format!("gltf has no {:?} scene", INSTANCE_SCENE))?;
+    let exported: Vec<u32> = tags.iter().map(|tag| tag.object_index).collect();
+    scene["extras"] = json!({ "exported_object_indices": exported });
+
+    let nodes = root
+        .get_mut("nodes")
+        .and_then(Value::as_array_mut)
+        .context("gltf has no nodes")?;
+
+    for tag in tags {
+        let node = nodes
+            .get_mut(tag.node_index)
+            .with_context(|| format!("gltf has no node {}", tag.node_index))?;
+        node["extras"] = json!({
+            "object_index": tag.object_index,
+            "model_table_index": tag.model_table_index,
+            "block_index": tag.block_index,
+        });
+    }
+
+    let gltf_file = File::create(gltf_path).with_path(gltf_path)?;
+    serde_json::to_writer(gltf_file, &root)?;
+
+    Ok(())
+}
+
+#[derive(Clap)]
+pub struct ImportGltfOpts {
+    #[clap(short, long, about = "edited gltf or glb exported by loi gltf")]
+    input_path: String,
+    #[clap(
+        short,
+        long,
+        about = "object list the gltf was exported from (loi or json manifest)"
+    )]
+    base_path: String,
+    #[clap(short, long, about = "output file (loi or json manifest)")]
+    output_path: String,
+}
+
+struct Instance {
+    node_index: usize,
+    object_index: u32,
+    model_table_index: u32,
+    translation: math::Vector3,
+    rotation: math::Matrix3,
+    scale: f32,
+}
+
+fn read_gltf_json(path: &Path) -> anyhow::Result<Value> {
+    let mut buf = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut buf))
+        .with_path(path)?;
+
+    if !buf.starts_with(b"glTF") {
+        return Ok(serde_json::from_slice(&buf)?);
+    }
+
+    // glb: 12 byte header followed by the JSON chunk
+    let chunk_header = buf.get(12..20).context("glb is missing its JSON chunk")?;
+    let chunk_length = u32::from_le_bytes(chunk_header[0..4].try_into()?) as usize;
+    anyhow::ensure!(
+        &chunk_header[4..8] == b"JSON",
+        "first glb chunk is not JSON"
+    );
+    let chunk = buf
+        .get(20..20 + chunk_length)
+        .context("glb JSON chunk is truncated")?;
+
+    Ok(serde_json::from_slice(chunk)?)
+}
+
+fn floats(value: &Value, expected: usize) -> Option<Vec<f32>> {
+    let floats = value
+        .as_array()?
+        .iter()
+        .map(|v| v.as_f64().map(|f| f as f32))
+        .collect::<Option<Vec<f32>>>()?;
+
+    if floats.len() == expected {
+        Some(floats)
+    } else {
+        None
+    }
+}
+
+fn extras_index(extras: &Value, key: &str) -> Option<u32> {
+    extras.get(key)?.as_f64().map(|f| f as u32)
+}
+
+/// Objects the gltf was exported with, collected from every scene so it still
+/// works if the editor renamed or split the instance scene. `None` if no
+/// scene records them.
+fn exported_object_indices(root: &Value) -> Option<HashSet<u32>> {
+    let lists: Vec<&Vec<Value>> = root
+        .get("scenes")?
+        .as_array()?
+        .iter()
+        .filter_map(|scene| {
+            scene
+                .get("extras")?
+                .get("exported_object_indices")?
+                .as_array()
+        })
+        .collect();
+
+    if lists.is_empty() {
+        return None;
+    }
+
+    Some(
+        lists
+            .into_iter()
+            .flatten()
+            .filter_map(|v| v.as_f64().map(|f| f as u32))
+            .collect(),
+    )
+}
+
+fn read_instance(node_index: usize, node: &Value) -> anyhow::Result<Option<Instance>> {
+    let extras = match node.get("extras") {
+        Some(extras) => extras,
+        None => return Ok(None),
+    };
+    let object_index = match extras_index(extras, "object_index") {
+        Some(object_index) => object_index,
+        None => return Ok(None),
+    };
+    let model_table_index =
+        extras_index(extras, "model_table_index").context("missing model_table_index in extras")?;
+
+    let (translation, rotation, scale) = match node.get("matrix") {
+        Some(matrix) => {
+            // column-major 4x4
+            let m = floats(matrix, 16).context("invalid matrix")?;
+            let columns = [[m[0], m[1], m[2]], [m[4], m[5], m[6]], [m[8], m[9], m[10]]];
+            let scales = columns.map(|c| (c[0] * c[0] + c[1] * c[1] + c[2] * c[2]).sqrt());
+
+            let mut rotation = [[0f32; 3]; 3];
+            for (col, column) in columns.iter().enumerate() {
+                for row in 0..3 {
+                    rotation[row][col] = if scales[col] > 0.0 {
+                        column[row] / scales[col]
+                    } else {
+                        0.0
+                    };
+                }
+            }
+
+            ([m[12], m[13], m[14]], rotation, scales)
+        }
+        None => {
+            let translation = match node.get("translation") {
+                Some(t) => floats(t, 3).context("invalid translation")?,
+                None => vec![0.0; 3],
+            };
+            let rotation = match node.get("rotation") {
+                Some(r) => floats(r, 4).context("invalid rotation")?,
+                None => vec![0.0, 0.0, 0.0, 1.0],
+            };
+            let scale = match node.get("scale") {
+                Some(s) => floats(s, 3).context("invalid scale")?,
+                None => vec![1.0; 3],
+            };
+
+            (
+                [translation[0], translation[1], translation[2]],
+                math::matrix_from_quaternion([rotation[0], rotation[1], rotation[2], rotation[3]]),
+                [scale[0], scale[1], scale[2]],
+            )
+        }
+    };
+
+    let uniform_scale = (scale[0] + scale[1] + scale[2]) / 3.0;
+    if scale.iter().any(|s| (s - uniform_scale).abs() > 1e-3) {
+        eprintln!(
+            "Node {} (object {}) has non-uniform scale {:?}, using {}",
+            node_index, object_index, scale, uniform_scale
+        );
+    }
+
+    Ok(Some(Instance {
+        node_index,
+        object_index,
+        model_table_index,
+        translation,
+        rotation,
+        scale: uniform_scale,
+    }))
+}
+
+pub fn process_import_gltf(import_gltf_opts: ImportGltfOpts) -> anyhow::Result<()> {
+    let mut loi = super::read_loi(Path::new(&import_gltf_opts.base_path))?;
+    let root = read_gltf_json(Path::new(&import_gltf_opts.input_path))?;
+
+    let nodes = root
+        .get("nodes")
+        .and_then(Value::as_array)
+        .context("gltf has no nodes")?;
+
+    let mut instances = Vec::new();
+    for (node_index, node) in nodes.iter().enumerate() {
+        if let Some(instance) =
+            read_instance(node_index, node).with_context(|| format!("node {}", node_index))?
+        {
+            instances.push(instance);
+        }
+    }
+
+    // The first node carrying an object_index updates that object, any
+    // further ones were duplicated in the editor and become new objects.
+    let mut updates: HashMap<u32, Instance> = HashMap::new();
+    let mut duplicates = Vec::new();
+    for instance in instances {
+        match updates.entry(instance.object_index) {
+            Entry::Occupied(_) => duplicates.push(instance),
+            Entry::Vacant(entry) => {
+                entry.insert(instance);
+            }
+        }
+    }
+
+    // Only objects that were exported and lost their node count as deleted,
+    // anything skipped at export (missing model, unparsable nif, outside the
+    // exported blocks) is kept.
+    let exported = exported_object_indices(&root);
+    if exported.is_none() {
+        eprintln!("gltf does not list its exported objects, no objects will be removed");
+    }
+
+    let mut removed = HashSet::new();
+    let mut found = HashSet::new();
+    let mut updated_count = 0;
+
+    for block in loi.blocks.iter_mut() {
+        for object in block.objects.iter_mut() {
+            match updates.get(&object.object_index) {
+                Some(instance) => {
+                    apply_instance(object, instance);
+                    found.insert(object.object_index);
+                    updated_count += 1;
+                }
+                None if exported
+                    .as_ref()
+                    .is_some_and(|exported| exported.contains(&object.object_index)) =>
+                {
+                    removed.insert(object.object_index);
+                }
+                None => {}
+            }
+        }
+    }
+
+    for object_index in updates.keys().filter(|i| !found.contains(i)) {
+        eprintln!(
+            "Object {} from gltf does not exist in base object list, skipping",
+            object_index
+        );
+    }
+
+    objects::remove_objects(&mut loi, &removed);
+
+    let mut added_count = 0;
+
+    for instance in duplicates.iter() {
+        let placement = instance.placement();
+        match objects::add_object_copy(&mut loi, instance.object_index, None, &placement) {
+            Some(_) => added_count += 1,
+            None => eprintln!(
+                "Node {} duplicates unknown object {}, skipping",
+                instance.node_index, instance.object_index
+            ),
+        }
+    }
+
+    super::write_loi_file(Path::new(&import_gltf_opts.output_path), &loi)?;
+
+    eprintln!(
+        "Updated {} objects, added {}, removed {}",
+        updated_count,
+        added_count,
+        removed.len()
+    );
+    if added_count > 0 {
+        eprintln!(
+            "New objects were placed in their source's block, run loi rebucket to reassign them"
+        );
+    }
+
+    Ok(())
+}
+
+impl Instance {
+    fn placement(&self) -> objects::Placement {
+        objects::Placement {
+            model_table_index: self.model_table_index,
+            position: (
+                self.translation[0],
+                self.translation[1],
+                self.translation[2],
+            ),
+            rotation: math::matrix_to_tuples(&self.rotation),
+            scale: self.scale,
+        }
+    }
+}
+
+fn apply_instance(object: &mut loi::BlockObject, instance: &Instance) {
+    let placement = instance.placement();
+    object.model_table_index = placement.model_table_index;
+    object.position = placement.position;
+    object.rotation = placement.rotation;
+    object.scale = placement.scale;
+}
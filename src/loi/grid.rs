@@ -0,0 +1,97 @@
+use std::path::Path;
+
+use anyhow::Context;
+use slidetown::parsers::lf;
+
+use crate::error::parse_file;
+
+/// Maps world positions onto the terrain block grid of an LF.
+///
+/// Blocks are numbered row by row, `index = y * size_x + x`, which is also how
+/// LOI blocks are laid out. The block origin and size in world units are fitted
+/// from the block positions stored in the LF unless a size is given.
+pub struct BlockGrid {
+    pub size_x: u32,
+    pub size_y: u32,
+    origin: [f32; 2],
+    block_size: [f32; 2],
+}
+
+impl BlockGrid {
+    pub fn from_lf_path(lf_path: &Path, block_size: Option<f32>) -> anyhow::Result<Self> {
+        let (lf, _) = parse_file(lf_path, lf::Lf::parse)?;
+
+        Self::from_lf(&lf, block_size)
+    }
+
+    pub fn from_lf(lf: &lf::Lf, block_size: Option<f32>) -> anyhow::Result<Self> {
+        let size_x = lf.header.size_x;
+        let size_y = lf.header.size_y;
+        anyhow::ensure!(size_x > 0 && size_y > 0, "LF grid has no dimensions");
+
+        // (grid coordinate, world position) per axis
+        let samples: Vec<([f32; 2], [f32; 2])> = lf
+            .blocks
+            .iter()
+            .map(|block| {
+                (
+                    [(block.index % size_x) as f32, (block.index / size_x) as f32],
+                    [block.position_x as f32, block.position_y as f32],
+                )
+            })
+            .collect();
+
+        let first = samples.first().context("LF contains no blocks")?;
+
+        let derived_size = |axis: usize| -> Option<f32> {
+            samples
+                .iter()
+                .find(|(grid, _)| grid[axis] != first.0[axis])
+                .map(|(grid, world)| (world[axis] - first.1[axis]) / (grid[axis] - first.0[axis]))
+                .filter(|size| *size > 0.0)
+        };
+
+        let fitted_size = match block_size {
+            Some(block_size) => [block_size, block_size],
+            // blocks are square, so a map that is a single row or column
+            // borrows the size from the axis that has more than one block
+            None => match (derived_size(0), derived_size(1)) {
+                (Some(x), Some(y)) => [x, y],
+                (Some(size), None) | (None, Some(size)) => [size, size],
+                (None, None) => anyhow::bail!(
+                    "could not derive block size from LF block positions, pass one explicitly"
+                ),
+            },
+        };
+
+        let mut origin = [0f32; 2];
+        for axis in 0..2 {
+            origin[axis] = first.1[axis] - first.0[axis] * fitted_size[axis];
+        }
+
+        Ok(Self {
+            size_x,
+            size_y,
+            origin,
+            block_size: fitted_size,
+        })
+    }
+
+    /// Grid coordinates of the block containing a world position, or `None`
+    /// if it falls outside the map. Only the horizontal x and y are used.
+    pub fn block_coordinates(&self, position: (f32, f32, f32)) -> Option<(u32, u32)> {
+        let x = ((position.0 - self.origin[0]) / self.block_size[0]).floor();
+        let y = ((position.1 - self.origin[1]) / self.block_size[1]).floor();
+
+        if x < 0.0 || y < 0.0 || x >= self.size_x as f32 || y >= self.size_y as f32 {
+            return None;
+        }
+
+        Some((x as u32, y as u32))
+    }
+
+    pub fn block_index(&self, position: (f32, f32, f32)) -> Option<u32> {
+        self.block_coordinates(position)
+            .map(|(x, y)| y * self.size_x + x)
+    }
+}
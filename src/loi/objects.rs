@@ -0,0 +1,383 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::Path,
+};
+
+use anyhow::Context;
+use clap::Clap;
+use slidetown::parsers::loi;
+
+use super::{grid, selection::parse_vector3};
+use crate::math;
+
+/// Where and what a newly added object should be.
+pub struct Placement {
+    pub model_table_index: u32,
+    pub position: (f32, f32, f32),
+    pub rotation: math::RotationTuples,
+    pub scale: f32,
+}
+
+/// Block and object position of an object in the block lists.
+pub fn find_object(loi: &loi::Loi, object_index: u32) -> Option<(usize, usize)> {
+    loi.blocks
+        .iter()
+        .enumerate()
+        .find_map(|(block_position, block)| {
+            block
+                .objects
+                .iter()
+                .position(|object| object.object_index == object_index)
+                .map(|object_position| (block_position, object_position))
+        })
+}
+
+pub fn next_object_index(loi: &loi::Loi) -> u32 {
+    loi.blocks
+        .iter()
+        .flat_map(|block| block.objects.iter().map(|object| object.object_index + 1))
+        .max()
+        .unwrap_or(0)
+}
+
+fn next_object_extra_index(loi: &loi::Loi) -> u32 {
+    loi.object_extras
+        .iter()
+        .map(|object_extra| object_extra.object_extra_index + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Copies every field of a placed object, the caller assigns a new index.
+/// An extra belongs to a single object, so the copy gets none.
+fn duplicate_object(object: &loi::BlockObject) -> loi::BlockObject {
+    loi::BlockObject {
+        unknown1: object.unknown1,
+        unknown2: object.unknown2,
+        unknown3: object.unknown3,
+        unknown4: object.unknown4,
+        object_index: object.object_index,
+        block_index: object.block_index,
+        model_table_index: object.model_table_index,
+        position: object.position,
+        rotation: object.rotation,
+        scale: object.scale,
+        unknown8: object.unknown8,
+        unknown9: object.unknown9,
+        object_extra_index: -1,
+        unknown11: object.unknown11,
+    }
+}
+
+fn duplicate_object_extra(object_extra: &loi::ObjectExtra) -> loi::ObjectExtra {
+    loi::ObjectExtra {
+        object_index: object_extra.object_index,
+        object_extra_index: object_extra.object_extra_index,
+        unknown3: object_extra.unknown3,
+        position: object_extra.position,
+        rotation: object_extra.rotation,
+        unknown4: object_extra.unknown4,
+        unknown5: object_extra.unknown5,
+    }
+}
+
+/// Adds a new object modelled after an existing one. The unknown fields are
+/// copied, an extra belonging to the source is duplicated and moved along, and
+/// if the source is in an object group the new object joins the group of its
+/// own block. Returns the new object index, or `None` if the source does not
+/// exist.
+pub fn add_object_copy(
+    loi: &mut loi::Loi,
+    source_object_index: u32,
+    target_block_position: Option<usize>,
+    placement: &Placement,
+) -> Option<u32> {
+    let (source_block_position, source_object_position) = find_object(loi, source_object_index)?;
+    let block_position = target_block_position.unwrap_or(source_block_position);
+
+    let object_index = next_object_index(loi);
+
+    let source = &loi.blocks[source_block_position].objects[source_object_position];
+    let mut object = duplicate_object(source);
+    object.object_index = object_index;
+    object.block_index = loi.blocks[block_position].block_index;
+    object.model_table_index = placement.model_table_index;
+    object.position = placement.position;
+    object.rotation = placement.rotation;
+    object.scale = placement.scale;
+
+    // Keep the extra at the same offset relative to its object
+    let source_extra = loi
+        .object_extras
+        .iter()
+        .find(|object_extra| object_extra.object_index == source_object_index);
+    if let Some(source_extra) = source_extra {
+        let relative_rotation = math::multiply(
+            &math::matrix_from_tuples(&placement.rotation),
+            &math::transpose(&math::matrix_from_tuples(&source.rotation)),
+        );
+        let offset = math::transform(
+            &relative_rotation,
+            [
+                source_extra.position.0 - source.position.0,
+                source_extra.position.1 - source.position.1,
+                source_extra.position.2 - source.position.2,
+            ],
+        );
+
+        let mut object_extra = duplicate_object_extra(source_extra);
+        object_extra.object_index = object_index;
+        object_extra.object_extra_index = next_object_extra_index(loi);
+        object_extra.position = (
+            placement.position.0 + offset[0],
+            placement.position.1 + offset[1],
+            placement.position.2 + offset[2],
+        );
+        object_extra.rotation = math::matrix_to_tuples(&math::multiply(
+            &relative_rotation,
+            &math::matrix_from_tuples(&source_extra.rotation),
+        ));
+
+        object.object_extra_index = object_extra.object_extra_index as i32;
+        loi.object_extras.push(object_extra);
+        loi.object_extra_count = loi.object_extras.len() as u32;
+    }
+
+    let source_grouped = loi.unknown_objects_5.iter().any(|unknown_object_5| {
+        unknown_object_5
+            .object_indices
+            .contains(&source_object_index)
+    });
+    if source_grouped {
+        // One group list per block, in block order
+        if let Some(unknown_object_5) = loi.unknown_objects_5.get_mut(block_position) {
+            unknown_object_5.object_indices.push(object_index);
+            unknown_object_5.object_count = unknown_object_5.object_indices.len() as u32;
+        }
+    }
+
+    let block = &mut loi.blocks[block_position];
+    block.objects.push(object);
+    block.object_count = block.objects.len() as u32;
+
+    Some(object_index)
+}
+
+/// Removes objects along with their extras and references in the trailing
+/// object index lists, keeping every count in sync.
+pub fn remove_objects(loi: &mut loi::Loi, object_indices: &HashSet<u32>) {
+    if object_indices.is_empty() {
+        return;
+    }
+
+    for block in loi.blocks.iter_mut() {
+        block
+            .objects
+            .retain(|object| !object_indices.contains(&object.object_index));
+        block.object_count = block.objects.len() as u32;
+    }
+
+    loi.object_extras
+        .retain(|object_extra| !object_indices.contains(&object_extra.object_index));
+    loi.object_extra_count = loi.object_extras.len() as u32;
+
+    for unknown_object_5 in loi.unknown_objects_5.iter_mut() {
+        unknown_object_5
+            .object_indices
+            .retain(|object_index| !object_indices.contains(object_index));
+        unknown_object_5.object_count = unknown_object_5.object_indices.len() as u32;
+    }
+}
+
+/// Renumbers object indices to be contiguous from 0, keeping their order.
+/// Returns how many objects got a new index.
+fn compact_object_indices(loi: &mut loi::Loi) -> usize {
+    let object_indices: BTreeSet<u32> = loi
+        .blocks
+        .iter()
+        .flat_map(|block| block.objects.iter().map(|object| object.object_index))
+        .collect();
+
+    let remap: HashMap<u32, u32> = object_indices
+        .into_iter()
+        .enumerate()
+        .filter(|&(new_index, old_index)| new_index as u32 != old_index)
+        .map(|(new_index, old_index)| (old_index, new_index as u32))
+        .collect();
+
+    if remap.is_empty() {
+        return 0;
+    }
+
+    for block in loi.blocks.iter_mut() {
+        for object in block.objects.iter_mut() {
+            if let Some(&new_index) = remap.get(&object.object_index) {
+                object.object_index = new_index;
+            }
+        }
+    }
+
+    for object_extra in loi.object_extras.iter_mut() {
+        if let Some(&new_index) = remap.get(&object_extra.object_index) {
+            object_extra.object_index = new_index;
+        }
+    }
+
+    // The other trailing lists are left alone, what their items refer to is
+    // not known (see analyze-unknowns).
+    for unknown_object_5 in loi.unknown_objects_5.iter_mut() {
+        for object_index in unknown_object_5.object_indices.iter_mut() {
+            if let Some(&new_index) = remap.get(object_index) {
+                *object_index = new_index;
+            }
+        }
+    }
+
+    remap.len()
+}
+
+#[derive(Clap)]
+pub struct AddObjectOpts {
+    #[clap(short, long, about = "input file (loi or json manifest)")]
+    input_path: String,
+    #[clap(short, long, about = "output file (loi or json manifest)")]
+    output_path: String,
+    #[clap(long, about = "model table index")]
+    model: u32,
+    #[clap(long, parse(try_from_str = parse_vector3), about = "position x,y,z")]
+    pos: math::Vector3,
+    #[clap(
+        long,
+        parse(try_from_str = parse_vector3),
+        about = "rotation as euler angles x,y,z in degrees"
+    )]
+    rot: Option<math::Vector3>,
+    #[clap(long, default_value = "1", about = "uniform scale")]
+    scale: f32,
+    #[clap(
+        long,
+        about = "object index to copy unknown fields, extra and groups from, defaults to an object with the same model"
+    )]
+    like: Option<u32>,
+    #[clap(long, about = "block index to place the object in")]
+    block: Option<u32>,
+    #[clap(
+        long,
+        about = "path to terrain0.lf to pick the block from the position"
+    )]
+    lf: Option<String>,
+    #[clap(
+        long,
+        about = "block size in world units, derived from the LF if omitted"
+    )]
+    block_size: Option<f32>,
+}
+
+pub fn process_add_object(add_object_opts: AddObjectOpts) -> anyhow::Result<()> {
+    let mut loi = super::read_loi(Path::new(&add_object_opts.input_path))?;
+
+    let template = match add_object_opts.like {
+        Some(object_index) => object_index,
+        None => {
+            let objects = || loi.blocks.iter().flat_map(|block| block.objects.iter());
+            objects()
+                .find(|object| object.model_table_index == add_object_opts.model)
+                .or_else(|| objects().next())
+                .map(|object| object.object_index)
+                .context("object list has no objects to use as a template, pass --like")?
+        }
+    };
+
+    let position = (
+        add_object_opts.pos[0],
+        add_object_opts.pos[1],
+        add_object_opts.pos[2],
+    );
+
+    let target_block_index = match (add_object_opts.block, &add_object_opts.lf) {
+        (Some(block_index), _) => Some(block_index),
+        (None, Some(lf_path)) => {
+            let grid =
+                grid::BlockGrid::from_lf_path(Path::new(lf_path), add_object_opts.block_size)?;
+            Some(
+                grid.block_index(position)
+                    .context("position is outside the map")?,
+            )
+        }
+        (None, None) => None,
+    };
+
+    let target_block_position = match target_block_index {
+        Some(block_index) => Some(
+            loi.blocks
+                .iter()
+                .position(|block| block.block_index == block_index)
+                .with_context(|| format!("block {} does not exist", block_index))?,
+        ),
+        None => {
+            eprintln!(
+                "No --block or --lf given, placing the object in the block of object {}",
+                template
+            );
+            None
+        }
+    };
+
+    let rotation = math::matrix_from_euler(add_object_opts.rot.unwrap_or([0.0; 3]));
+    let placement = Placement {
+        model_table_index: add_object_opts.model,
+        position,
+        rotation: math::matrix_to_tuples(&rotation),
+        scale: add_object_opts.scale,
+    };
+
+    let object_index = add_object_copy(&mut loi, template, target_block_position, &placement)
+        .with_context(|| format!("template object {} does not exist", template))?;
+
+    super::write_loi_file(Path::new(&add_object_opts.output_path), &loi)?;
+
+    eprintln!("Added object {} based on object {}", object_index, template);
+
+    Ok(())
+}
+
+#[derive(Clap)]
+pub struct RemoveObjectOpts {
+    #[clap(short, long, about = "input file (loi or json manifest)")]
+    input_path: String,
+    #[clap(short, long, about = "output file (loi or json manifest)")]
+    output_path: String,
+    #[clap(
+        long,
+        required = true,
+        about = "object index to remove, can be repeated"
+    )]
+    index: Vec<u32>,
+    #[clap(long, about = "renumber object indices to be contiguous afterwards")]
+    compact: bool,
+}
+
+pub fn process_remove_object(remove_object_opts: RemoveObjectOpts) -> anyhow::Result<()> {
+    let mut loi = super::read_loi(Path::new(&remove_object_opts.input_path))?;
+
+    let object_indices: HashSet<u32> = remove_object_opts.index.iter().copied().collect();
+    for object_index in object_indices.iter() {
+        anyhow::ensure!(
+            find_object(&loi, *object_index).is_some(),
+            "object {} does not exist",
+            object_index
+        );
+    }
+
+    remove_objects(&mut loi, &object_indices);
+    eprintln!("Removed {} objects", object_indices.len());
+
+    if remove_object_opts.compact {
+        let renumbered = compact_object_indices(&mut loi);
+        eprintln!("Renumbered {} objects", renumbered);
+    }
+
+    super::write_loi_file(Path::new(&remove_object_opts.output_path), &loi)?;
+
+    Ok(())
+}
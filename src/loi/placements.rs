@@ -0,0 +1,317 @@
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use anyhow::Context;
+use clap::Clap;
+use slidetown::parsers::loi;
+
+use crate::math;
+
+#[derive(Clap)]
+pub struct ExportCsvOpts {
+    #[clap(short, long, about = "input file (loi or json manifest)")]
+    input_path: String,
+    #[clap(short, long, about = "output file (.csv or .tsv)")]
+    output_path: String,
+    #[clap(
+        short,
+        long,
+        default_value = "matrix",
+        about = "rotation columns: matrix, euler (degrees) or quaternion, only matrix round-trips exactly"
+    )]
+    rotation: RotationFormat,
+}
+
+#[derive(Clap)]
+pub struct ImportCsvOpts {
+    #[clap(short, long, about = "input file (.csv or .tsv)")]
+    input_path: String,
+    #[clap(
+        short,
+        long,
+        about = "object list the placements were exported from (loi or json manifest)"
+    )]
+    base_path: String,
+    #[clap(short, long, about = "output file (loi or json manifest)")]
+    output_path: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum RotationFormat {
+    Euler,
+    Quaternion,
+    Matrix,
+}
+
+impl FromStr for RotationFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "euler" => Ok(RotationFormat::Euler),
+            "quaternion" => Ok(RotationFormat::Quaternion),
+            "matrix" => Ok(RotationFormat::Matrix),
+            _ => anyhow::bail!("unknown rotation format {:?}", s),
+        }
+    }
+}
+
+impl RotationFormat {
+    fn columns(self) -> &'static [&'static str] {
+        match self {
+            RotationFormat::Euler => &["rotation_x", "rotation_y", "rotation_z"],
+            RotationFormat::Quaternion => {
+                &["rotation_qx", "rotation_qy", "rotation_qz", "rotation_qw"]
+            }
+            RotationFormat::Matrix => &[
+                "rotation_00",
+                "rotation_01",
+                "rotation_02",
+                "rotation_10",
+                "rotation_11",
+                "rotation_12",
+                "rotation_20",
+                "rotation_21",
+                "rotation_22",
+            ],
+        }
+    }
+
+    fn detect(columns: &HashMap<String, usize>) -> anyhow::Result<Self> {
+        [
+            RotationFormat::Matrix,
+            RotationFormat::Quaternion,
+            RotationFormat::Euler,
+        ]
+        .iter()
+        .copied()
+        .find(|format| format.columns().iter().all(|c| columns.contains_key(*c)))
+        .context("no complete set of rotation columns found in header")
+    }
+
+    fn values(self, rotation: &math::Matrix3) -> Vec<f32> {
+        match self {
+            RotationFormat::Euler => math::euler_from_matrix(rotation).to_vec(),
+            RotationFormat::Quaternion => math::quaternion_from_matrix(rotation).to_vec(),
+            RotationFormat::Matrix => rotation.iter().flatten().copied().collect(),
+        }
+    }
+
+    fn matrix(self, values: &[f32]) -> math::Matrix3 {
+        match self {
+            RotationFormat::Euler => math::matrix_from_euler([values[0], values[1], values[2]]),
+            RotationFormat::Quaternion => {
+                math::matrix_from_quaternion([values[0], values[1], values[2], values[3]])
+            }
+            RotationFormat::Matrix => [
+                [values[0], values[1], values[2]],
+                [values[3], values[4], values[5]],
+                [values[6], values[7], values[8]],
+            ],
+        }
+    }
+}
+
+const LEADING_COLUMNS: &[&str] = &[
+    "block_index",
+    "object_index",
+    "model_table_index",
+    "position_x",
+    "position_y",
+    "position_z",
+];
+
+const TRAILING_COLUMNS: &[&str] = &[
+    "scale",
+    "unknown1",
+    "unknown2",
+    "unknown3",
+    "unknown4",
+    "unknown8",
+    "unknown9",
+    "object_extra_index",
+    "unknown11",
+];
+
+fn delimiter_for(path: &Path) -> u8 {
+    match path.extension() {
+        Some(ext) if ext.eq_ignore_ascii_case("tsv") => b'\t',
+        _ => b',',
+    }
+}
+
+pub fn process_export_csv(export_csv_opts: ExportCsvOpts) -> anyhow::Result<()> {
+    let loi = super::read_loi(Path::new(&export_csv_opts.input_path))?;
+
+    let output_path = Path::new(&export_csv_opts.output_path);
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter_for(output_path))
+        .from_path(output_path)?;
+
+    let rotation_format = export_csv_opts.rotation;
+
+    writer.write_record(
+        LEADING_COLUMNS
+            .iter()
+            .chain(rotation_format.columns())
+            .chain(TRAILING_COLUMNS),
+    )?;
+
+    let mut row_count = 0;
+
+    for block in loi.blocks.iter() {
+        for object in block.objects.iter() {
+            let rotation = math::matrix_from_tuples(&object.rotation);
+
+            let mut record = vec![
+                block.block_index.to_string(),
+                object.object_index.to_string(),
+                object.model_table_index.to_string(),
+                object.position.0.to_string(),
+                object.position.1.to_string(),
+                object.position.2.to_string(),
+            ];
+            record.extend(
+                rotation_format
+                    .values(&rotation)
+                    .iter()
+                    .map(|v| v.to_string()),
+            );
+            record.extend(vec![
+                object.scale.to_string(),
+                object.unknown1.to_string(),
+                object.unknown2.to_string(),
+                object.unknown3.to_string(),
+                object.unknown4.to_string(),
+                object.unknown8.to_string(),
+                object.unknown9.to_string(),
+                object.object_extra_index.to_string(),
+                object.unknown11.to_string(),
+            ]);
+
+            writer.write_record(&record)?;
+            row_count += 1;
+        }
+    }
+
+    writer.flush()?;
+
+    eprintln!("Exported {} object placements", row_count);
+
+    Ok(())
+}
+
+struct Row<'a> {
+    record: &'a csv::StringRecord,
+    columns: &'a HashMap<String, usize>,
+}
+
+impl<'a> Row<'a> {
+    fn get<T>(&self, name: &str) -> anyhow::Result<T>
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let column = *self
+            .columns
+            .get(name)
+            .with_context(|| format!("missing column {}", name))?;
+        let value = self.record.get(column).unwrap_or("").trim();
+
+        value
+            .parse()
+            .with_context(|| format!("invalid value {:?} in column {}", value, name))
+    }
+}
+
+pub fn process_import_csv(import_csv_opts: ImportCsvOpts) -> anyhow::Result<()> {
+    let mut loi = super::read_loi(Path::new(&import_csv_opts.base_path))?;
+
+    let input_path = Path::new(&import_csv_opts.input_path);
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter_for(input_path))
+        .from_path(input_path)?;
+
+    let columns: HashMap<String, usize> = reader
+        .headers()?
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.trim().to_string(), i))
+        .collect();
+
+    let rotation_format = RotationFormat::detect(&columns)?;
+
+    let block_positions: HashMap<u32, usize> = loi
+        .blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| (block.block_index, i))
+        .collect();
+
+    for block in loi.blocks.iter_mut() {
+        block.objects.clear();
+    }
+
+    for (row_index, record) in reader.records().enumerate() {
+        // header is line 1
+        let line = row_index + 2;
+        let record = record?;
+        let row = Row {
+            record: &record,
+            columns: &columns,
+        };
+
+        let object =
+            read_object(&row, rotation_format).with_context(|| format!("line {}", line))?;
+
+        let &block_position = block_positions.get(&object.block_index).with_context(|| {
+            format!(
+                "line {}: block_index {} does not exist in base object list",
+                line, object.block_index
+            )
+        })?;
+
+        loi.blocks[block_position].objects.push(object);
+    }
+
+    let mut row_count = 0;
+    for block in loi.blocks.iter_mut() {
+        block.object_count = block.objects.len() as u32;
+        row_count += block.object_count;
+    }
+
+    super::write_loi_file(Path::new(&import_csv_opts.output_path), &loi)?;
+
+    eprintln!("Imported {} object placements", row_count);
+
+    Ok(())
+}
+
+fn read_object(row: &Row, rotation_format: RotationFormat) -> anyhow::Result<loi::BlockObject> {
+    let rotation_values = rotation_format
+        .columns()
+        .iter()
+        .map(|column| row.get::<f32>(column))
+        .collect::<anyhow::Result<Vec<f32>>>()?;
+    let rotation = rotation_format.matrix(&rotation_values);
+
+    Ok(loi::BlockObject {
+        unknown1: row.get("unknown1")?,
+        unknown2: row.get("unknown2")?,
+        unknown3: row.get("unknown3")?,
+        unknown4: row.get("unknown4")?,
+        object_index: row.get("object_index")?,
+        block_index: row.get("block_index")?,
+        model_table_index: row.get("model_table_index")?,
+        position: (
+            row.get("position_x")?,
+            row.get("position_y")?,
+            row.get("position_z")?,
+        ),
+        rotation: math::matrix_to_tuples(&rotation),
+        scale: row.get("scale")?,
+        unknown8: row.get("unknown8")?,
+        unknown9: row.get("unknown9")?,
+        object_extra_index: row.get("object_extra_index")?,
+        unknown11: row.get("unknown11")?,
+    })
+}
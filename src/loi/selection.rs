@@ -0,0 +1,199 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::Context;
+use clap::Clap;
+use slidetown::parsers::{lof, loi};
+
+use crate::{error::parse_file, math};
+
+/// Parses `x,y,z` into a vector.
+pub fn parse_vector3(s: &str) -> anyhow::Result<math::Vector3> {
+    let components = s
+        .split(',')
+        .map(|c| c.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>()
+        .with_context(|| format!("invalid vector {:?}", s))?;
+
+    match components.as_slice() {
+        &[x, y, z] => Ok([x, y, z]),
+        _ => anyhow::bail!("expected x,y,z but got {:?}", s),
+    }
+}
+
+/// Inclusive range of block indices, written as `N` or `START-END`.
+#[derive(Clone, Copy)]
+pub struct BlockRange {
+    start: u32,
+    end: u32,
+}
+
+impl FromStr for BlockRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = match s.split_once('-') {
+            Some((start, end)) => (start.trim().parse()?, end.trim().parse()?),
+            None => {
+                let index = s.trim().parse()?;
+                (index, index)
+            }
+        };
+        anyhow::ensure!(start <= end, "block range {:?} is reversed", s);
+
+        Ok(BlockRange { start, end })
+    }
+}
+
+impl BlockRange {
+    pub fn contains(&self, block_index: u32) -> bool {
+        (self.start..=self.end).contains(&block_index)
+    }
+}
+
+#[derive(Clap)]
+pub struct SelectionOpts {
+    #[clap(long, about = "model table index to select, can be repeated")]
+    model: Vec<u32>,
+    #[clap(
+        long,
+        about = "select models whose name or file name contains this, requires --lof-path"
+    )]
+    name: Option<String>,
+    #[clap(long, about = "path to modeltable0.LOF for model names")]
+    lof_path: Option<String>,
+    #[clap(
+        long,
+        about = "block index or range START-END to select, can be repeated"
+    )]
+    blocks: Vec<BlockRange>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_vector3),
+        about = "bounding box minimum x,y,z"
+    )]
+    min: Option<math::Vector3>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_vector3),
+        about = "bounding box maximum x,y,z"
+    )]
+    max: Option<math::Vector3>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_vector3),
+        about = "select around point x,y,z, requires --radius"
+    )]
+    near: Option<math::Vector3>,
+    #[clap(long, about = "radius around --near")]
+    radius: Option<f32>,
+}
+
+/// Resolved object filter, every given criterion has to match.
+pub struct Selection {
+    model_indices: Option<HashSet<u32>>,
+    blocks: Vec<BlockRange>,
+    min: math::Vector3,
+    max: math::Vector3,
+    sphere: Option<(math::Vector3, f32)>,
+    model_names: Option<HashMap<u32, String>>,
+}
+
+impl SelectionOpts {
+    pub fn resolve(&self) -> anyhow::Result<Selection> {
+        let mut model_indices: Option<HashSet<u32>> = if self.model.is_empty() {
+            None
+        } else {
+            Some(self.model.iter().copied().collect())
+        };
+
+        let model_names = match &self.lof_path {
+            Some(lof_path) => {
+                let (lof, _) = parse_file(Path::new(lof_path), lof::Lof::parse)?;
+
+                if let Some(name) = &self.name {
+                    let name = name.to_lowercase();
+                    let matching = lof
+                        .models
+                        .iter()
+                        .filter(|m| {
+                            m.name.to_lowercase().contains(&name)
+                                || m.file_name.to_lowercase().contains(&name)
+                        })
+                        .map(|m| m.index)
+                        .collect::<HashSet<u32>>();
+
+                    model_indices = Some(match model_indices {
+                        Some(model_indices) => &model_indices & &matching,
+                        None => matching,
+                    });
+                }
+
+                Some(
+                    lof.models
+                        .into_iter()
+                        .map(|m| (m.index, m.name))
+                        .collect::<HashMap<u32, String>>(),
+                )
+            }
+            None if self.name.is_some() => anyhow::bail!("--name requires --lof-path"),
+            None => None,
+        };
+
+        let sphere = match (self.near, self.radius) {
+            (Some(near), Some(radius)) => Some((near, radius)),
+            (None, None) => None,
+            _ => anyhow::bail!("--near and --radius have to be given together"),
+        };
+
+        Ok(Selection {
+            model_indices,
+            blocks: self.blocks.clone(),
+            min: self.min.unwrap_or([f32::NEG_INFINITY; 3]),
+            max: self.max.unwrap_or([f32::INFINITY; 3]),
+            sphere,
+            model_names,
+        })
+    }
+}
+
+impl Selection {
+    pub fn matches(&self, block: &loi::Block, object: &loi::BlockObject) -> bool {
+        if let Some(model_indices) = &self.model_indices {
+            if !model_indices.contains(&object.model_table_index) {
+                return false;
+            }
+        }
+
+        if !self.blocks.is_empty() && !self.blocks.iter().any(|r| r.contains(block.block_index)) {
+            return false;
+        }
+
+        let position = [object.position.0, object.position.1, object.position.2];
+
+        if (0..3).any(|axis| position[axis] < self.min[axis] || position[axis] > self.max[axis]) {
+            return false;
+        }
+
+        if let Some((center, radius)) = self.sphere {
+            let distance_squared: f32 = (0..3)
+                .map(|axis| (position[axis] - center[axis]).powi(2))
+                .sum();
+            if distance_squared > radius * radius {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    pub fn model_name(&self, model_table_index: u32) -> Option<&str> {
+        self.model_names
+            .as_ref()
+            .and_then(|names| names.get(&model_table_index))
+            .map(String::as_str)
+    }
+}
@@ -0,0 +1,189 @@
+use std::{collections::HashSet, path::Path, str::FromStr};
+
+use clap::Clap;
+
+use super::selection::{parse_vector3, SelectionOpts};
+use crate::math;
+
+#[derive(Clap)]
+pub struct TransformOpts {
+    #[clap(short, long, about = "input file (loi or json manifest)")]
+    input_path: String,
+    #[clap(short, long, about = "output file (loi or json manifest)")]
+    output_path: String,
+    #[clap(flatten)]
+    selection: SelectionOpts,
+    #[clap(
+        long,
+        parse(try_from_str = parse_vector3),
+        about = "translation x,y,z applied last"
+    )]
+    translate: Option<math::Vector3>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_vector3),
+        about = "rotation as euler angles x,y,z in degrees around the pivot"
+    )]
+    rotate: Option<math::Vector3>,
+    #[clap(long, about = "uniform scale around the pivot")]
+    scale: Option<f32>,
+    #[clap(long, about = "mirror across the pivot along axis x, y or z")]
+    mirror: Option<Axis>,
+    #[clap(
+        long,
+        parse(try_from_str = parse_vector3),
+        about = "pivot x,y,z, defaults to the center of the selection"
+    )]
+    pivot: Option<math::Vector3>,
+    #[clap(long, about = "replace the model table index of selected objects")]
+    set_model: Option<u32>,
+}
+
+#[derive(Clone, Copy)]
+struct Axis(usize);
+
+impl FromStr for Axis {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "x" | "X" => Ok(Axis(0)),
+            "y" | "Y" => Ok(Axis(1)),
+            "z" | "Z" => Ok(Axis(2)),
+            _ => anyhow::bail!("unknown axis {:?}, expected x, y or z", s),
+        }
+    }
+}
+
+/// Rigid transform applied to positions around a pivot. Orientations are
+/// rotated, and mirrored by conjugation so they stay proper rotations.
+struct Transform {
+    pivot: math::Vector3,
+    linear: math::Matrix3,
+    orientation: math::Matrix3,
+    mirror: Option<math::Matrix3>,
+    scale: f32,
+    translation: math::Vector3,
+}
+
+impl Transform {
+    fn position(&self, position: (f32, f32, f32)) -> (f32, f32, f32) {
+        let relative = [
+            position.0 - self.pivot[0],
+            position.1 - self.pivot[1],
+            position.2 - self.pivot[2],
+        ];
+        let moved = math::transform(&self.linear, relative);
+
+        (
+            moved[0] * self.scale + self.pivot[0] + self.translation[0],
+            moved[1] * self.scale + self.pivot[1] + self.translation[1],
+            moved[2] * self.scale + self.pivot[2] + self.translation[2],
+        )
+    }
+
+    fn rotation(&self, rotation: &math::RotationTuples) -> math::RotationTuples {
+        let mut matrix = math::matrix_from_tuples(rotation);
+        if let Some(mirror) = &self.mirror {
+            matrix = math::multiply(&math::multiply(mirror, &matrix), mirror);
+        }
+        matrix = math::multiply(&self.orientation, &matrix);
+
+        math::matrix_to_tuples(&matrix)
+    }
+}
+
+pub fn process_transform(transform_opts: TransformOpts) -> anyhow::Result<()> {
+    let mut loi = super::read_loi(Path::new(&transform_opts.input_path))?;
+    let selection = transform_opts.selection.resolve()?;
+
+    let mut selected = HashSet::new();
+    let mut center = [0f32; 3];
+
+    for block in loi.blocks.iter() {
+        for object in block.objects.iter() {
+            if selection.matches(block, object) {
+                selected.insert(object.object_index);
+                center[0] += object.position.0;
+                center[1] += object.position.1;
+                center[2] += object.position.2;
+            }
+        }
+    }
+
+    if selected.is_empty() {
+        eprintln!("No objects selected");
+        return Ok(());
+    }
+
+    let pivot = transform_opts
+        .pivot
+        .unwrap_or_else(|| center.map(|c| c / selected.len() as f32));
+
+    let orientation = transform_opts
+        .rotate
+        .map_or(math::IDENTITY, math::matrix_from_euler);
+    let mirror = transform_opts
+        .mirror
+        .map(|Axis(axis)| math::mirror_matrix(axis));
+    let linear = match &mirror {
+        Some(mirror) => math::multiply(&orientation, mirror),
+        None => orientation,
+    };
+
+    let transform = Transform {
+        pivot,
+        linear,
+        orientation,
+        mirror,
+        scale: transform_opts.scale.unwrap_or(1.0),
+        translation: transform_opts.translate.unwrap_or([0.0; 3]),
+    };
+
+    for block in loi.blocks.iter_mut() {
+        for object in block.objects.iter_mut() {
+            if !selected.contains(&object.object_index) {
+                continue;
+            }
+
+            object.position = transform.position(object.position);
+            object.rotation = transform.rotation(&object.rotation);
+            object.scale *= transform.scale;
+
+            if let Some(model_table_index) = transform_opts.set_model {
+                object.model_table_index = model_table_index;
+            }
+        }
+    }
+
+    let mut extra_count = 0;
+    for object_extra in loi.object_extras.iter_mut() {
+        if !selected.contains(&object_extra.object_index) {
+            continue;
+        }
+
+        object_extra.position = transform.position(object_extra.position);
+        object_extra.rotation = transform.rotation(&object_extra.rotation);
+        extra_count += 1;
+    }
+
+    super::write_loi_file(Path::new(&transform_opts.output_path), &loi)?;
+
+    eprintln!(
+        "Transformed {} objects and {} object extras around ({}, {}, {})",
+        selected.len(),
+        extra_count,
+        pivot[0],
+        pivot[1],
+        pivot[2]
+    );
+    if transform_opts.translate.is_some()
+        || transform_opts.rotate.is_some()
+        || transform_opts.scale.is_some()
+        || transform_opts.mirror.is_some()
+    {
+        eprintln!("Objects may have left their blocks, run loi rebucket to reassign them");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,285 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use clap::Clap;
+use slidetown::parsers::loi;
+
+#[derive(Clap)]
+pub struct AnalyzeUnknownsOpts {
+    #[clap(short, long, about = "input file (loi or json manifest)")]
+    input_path: String,
+}
+
+/// Everything the trailing sections could plausibly refer to.
+struct Context {
+    block_count: usize,
+    /// block_index of each block in file order
+    block_order: Vec<u32>,
+    object_count: usize,
+    object_extra_count: usize,
+    /// object_index -> block_index
+    object_blocks: HashMap<u32, u32>,
+    block_indices: HashSet<u32>,
+    object_extra_indices: HashSet<u32>,
+    model_indices: HashSet<u32>,
+}
+
+impl Context {
+    fn new(loi: &loi::Loi) -> Self {
+        let mut object_blocks = HashMap::new();
+        let mut model_indices = HashSet::new();
+
+        for block in loi.blocks.iter() {
+            for object in block.objects.iter() {
+                object_blocks.insert(object.object_index, block.block_index);
+                model_indices.insert(object.model_table_index);
+            }
+        }
+
+        Context {
+            block_count: loi.blocks.len(),
+            block_order: loi.blocks.iter().map(|b| b.block_index).collect(),
+            object_count: object_blocks.len(),
+            object_extra_count: loi.object_extras.len(),
+            object_blocks,
+            block_indices: loi.blocks.iter().map(|b| b.block_index).collect(),
+            object_extra_indices: loi
+                .object_extras
+                .iter()
+                .map(|e| e.object_extra_index)
+                .collect(),
+            model_indices,
+        }
+    }
+}
+
+/// A trailing section viewed as a list of entries, each holding a list of
+/// `u32` items and optionally one extra scalar.
+struct Section<'a> {
+    name: &'a str,
+    entries: Vec<&'a [u32]>,
+    scalars: Option<Vec<u32>>,
+}
+
+fn fraction(part: usize, total: usize) -> String {
+    if total == 0 {
+        return "n/a".to_string();
+    }
+    format!(
+        "{}/{} ({:.1}%)",
+        part,
+        total,
+        part as f32 / total as f32 * 100.0
+    )
+}
+
+fn describe_count(count: usize, context: &Context) -> String {
+    let mut matches = Vec::new();
+    if count == context.block_count {
+        matches.push("block count");
+    }
+    if count == context.object_count {
+        matches.push("object count");
+    }
+    if count == context.object_extra_count {
+        matches.push("object extra count");
+    }
+
+    if matches.is_empty() {
+        count.to_string()
+    } else {
+        format!("{} (same as {})", count, matches.join(", "))
+    }
+}
+
+fn analyze_section(section: &Section, context: &Context) {
+    println!("[{}]", section.name);
+    println!(
+        "  Entries: {}",
+        describe_count(section.entries.len(), context)
+    );
+
+    let items: Vec<u32> = section
+        .entries
+        .iter()
+        .flat_map(|e| e.iter().copied())
+        .collect();
+    let empty_entries = section.entries.iter().filter(|e| e.is_empty()).count();
+
+    println!("  Items: {}, empty entries: {}", items.len(), empty_entries);
+    if let (Some(min), Some(max)) = (items.iter().min(), items.iter().max()) {
+        println!("  Item range: {}..={}", min, max);
+    }
+
+    let as_objects = items
+        .iter()
+        .filter(|i| context.object_blocks.contains_key(i))
+        .count();
+    let as_blocks = items
+        .iter()
+        .filter(|i| context.block_indices.contains(i))
+        .count();
+    let as_extras = items
+        .iter()
+        .filter(|i| context.object_extra_indices.contains(i))
+        .count();
+    let as_models = items
+        .iter()
+        .filter(|i| context.model_indices.contains(i))
+        .count();
+
+    println!(
+        "  Items resolving as object_index: {}",
+        fraction(as_objects, items.len())
+    );
+    println!(
+        "  Items resolving as block_index: {}",
+        fraction(as_blocks, items.len())
+    );
+    println!(
+        "  Items resolving as object_extra_index: {}",
+        fraction(as_extras, items.len())
+    );
+    println!(
+        "  Items resolving as model_table_index: {}",
+        fraction(as_models, items.len())
+    );
+
+    let sorted_entries = section
+        .entries
+        .iter()
+        .filter(|e| e.windows(2).all(|w| w[0] <= w[1]))
+        .count();
+    println!(
+        "  Entries sorted ascending: {}",
+        fraction(sorted_entries, section.entries.len())
+    );
+
+    let distinct: HashSet<u32> = items.iter().copied().collect();
+    let disjoint = distinct.len() == items.len();
+    println!(
+        "  Distinct items: {}{}",
+        distinct.len(),
+        if disjoint { " (no item repeats)" } else { "" }
+    );
+
+    // Entries lined up with blocks: does entry N only reference objects
+    // placed in block N?
+    let per_block = section.entries.len() == context.block_count;
+    let mut same_block_entries = 0;
+    let mut non_empty_entries = 0;
+    if per_block {
+        for (entry, block_index) in section.entries.iter().zip(context.block_order.iter()) {
+            if entry.is_empty() {
+                continue;
+            }
+            non_empty_entries += 1;
+            if entry
+                .iter()
+                .all(|i| context.object_blocks.get(i) == Some(block_index))
+            {
+                same_block_entries += 1;
+            }
+        }
+        println!(
+            "  Entries only referencing objects of the block at the same position: {}",
+            fraction(same_block_entries, non_empty_entries)
+        );
+    }
+
+    if let Some(scalars) = &section.scalars {
+        let as_blocks = scalars
+            .iter()
+            .filter(|s| context.block_indices.contains(s))
+            .count();
+        let as_objects = scalars
+            .iter()
+            .filter(|s| context.object_blocks.contains_key(s))
+            .count();
+        println!(
+            "  Scalar field range: {:?}..={:?}, as block_index: {}, as object_index: {}",
+            scalars.iter().min(),
+            scalars.iter().max(),
+            fraction(as_blocks, scalars.len()),
+            fraction(as_objects, scalars.len())
+        );
+    }
+
+    let hypothesis = if items.is_empty() {
+        "no items, nothing to correlate"
+    } else if as_objects == items.len() && per_block && same_block_entries == non_empty_entries {
+        "per-block lists of the objects placed in that block"
+    } else if as_objects == items.len() && disjoint {
+        "disjoint groups of placed objects"
+    } else if as_objects == items.len() {
+        "overlapping sets of placed objects, e.g. LOD or visibility sets"
+    } else if as_blocks == items.len() && per_block {
+        "per-block lists of other blocks, e.g. neighbour or visibility sets"
+    } else if as_blocks == items.len() {
+        "lists of block indices"
+    } else {
+        "no consistent interpretation"
+    };
+    println!("  Hypothesis: {}", hypothesis);
+    println!();
+}
+
+pub fn process_analyze_unknowns(analyze_opts: AnalyzeUnknownsOpts) -> anyhow::Result<()> {
+    let loi = super::read_loi(Path::new(&analyze_opts.input_path))?;
+    let context = Context::new(&loi);
+
+    println!(
+        "Blocks: {}, objects: {}, object extras: {}",
+        context.block_count, context.object_count, context.object_extra_count
+    );
+    println!();
+
+    let sections = [
+        Section {
+            name: "unknown_objects_2",
+            entries: loi
+                .unknown_objects_2
+                .iter()
+                .map(|u| u.items.as_slice())
+                .collect(),
+            scalars: None,
+        },
+        Section {
+            name: "unknown_objects_3",
+            entries: loi
+                .unknown_objects_3
+                .iter()
+                .map(|u| u.items.as_slice())
+                .collect(),
+            scalars: Some(loi.unknown_objects_3.iter().map(|u| u.unknown1).collect()),
+        },
+        Section {
+            name: "unknown_objects_4",
+            entries: loi
+                .unknown_objects_4
+                .iter()
+                .map(|u| u.items.as_slice())
+                .collect(),
+            scalars: Some(loi.unknown_objects_4.iter().map(|u| u.unknown1).collect()),
+        },
+        Section {
+            name: "unknown_objects_5",
+            entries: loi
+                .unknown_objects_5
+                .iter()
+                .map(|u| u.object_indices.as_slice())
+                .collect(),
+            scalars: None,
+        },
+    ];
+
+    for section in sections.iter() {
+        analyze_section(section, &context);
+    }
+
+    println!("unknown_objects_5 can be unpacked as object_groups with loi unpack --decode");
+
+    Ok(())
+}
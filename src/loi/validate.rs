@@ -0,0 +1,306 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+};
+
+use clap::Clap;
+use slidetown::parsers::{lof, loi};
+
+use crate::error::parse_file;
+
+#[derive(Clap)]
+pub struct ValidateOpts {
+    #[clap(short, long, about = "input file (loi or json manifest)")]
+    input_path: String,
+    #[clap(
+        long,
+        about = "path to modeltable0.LOF to check model references against"
+    )]
+    lof_path: Option<String>,
+}
+
+struct Issue {
+    location: String,
+    message: String,
+}
+
+#[derive(Default)]
+struct Issues(Vec<Issue>);
+
+impl Issues {
+    fn push(&mut self, location: impl Into<String>, message: impl Into<String>) {
+        self.0.push(Issue {
+            location: location.into(),
+            message: message.into(),
+        });
+    }
+
+    fn count(&mut self, location: impl Into<String>, name: &str, count: u32, actual: usize) {
+        if count as usize != actual {
+            self.push(
+                location,
+                format!("{} is {} but there are {} entries", name, count, actual),
+            );
+        }
+    }
+}
+
+pub fn process_validate(validate_opts: ValidateOpts) -> anyhow::Result<()> {
+    let loi = super::read_loi(Path::new(&validate_opts.input_path))?;
+
+    let model_indices = match validate_opts.lof_path {
+        Some(lof_path) => {
+            let (lof, _) = parse_file(Path::new(&lof_path), lof::Lof::parse)?;
+            Some(lof.models.iter().map(|m| m.index).collect::<HashSet<u32>>())
+        }
+        None => None,
+    };
+
+    let issues = validate(&loi, model_indices.as_ref());
+
+    for issue in issues.0.iter() {
+        println!("{}: {}", issue.location, issue.message);
+    }
+
+    if issues.0.is_empty() {
+        println!("No issues found");
+        Ok(())
+    } else {
+        anyhow::bail!("Found {} issues", issues.0.len())
+    }
+}
+
+fn validate(loi: &loi::Loi, model_indices: Option<&HashSet<u32>>) -> Issues {
+    let mut issues = Issues::default();
+
+    issues.count(
+        "header",
+        "block_count",
+        loi.header.block_count,
+        loi.blocks.len(),
+    );
+
+    // object_index -> (location, object_extra_index), negative indices mean
+    // the object has no extra
+    let mut objects: BTreeMap<u32, (String, Option<u32>)> = BTreeMap::new();
+
+    for (block_position, block) in loi.blocks.iter().enumerate() {
+        let block_location = format!(
+            "blocks[{}] (block_index {})",
+            block_position, block.block_index
+        );
+
+        issues.count(
+            block_location.as_str(),
+            "object_count",
+            block.object_count,
+            block.objects.len(),
+        );
+
+        for (object_position, object) in block.objects.iter().enumerate() {
+            let location = format!(
+                "{} objects[{}] (object_index {})",
+                block_location, object_position, object.object_index
+            );
+
+            if object.block_index != block.block_index {
+                issues.push(
+                    location.as_str(),
+                    format!(
+                        "block_index {} does not match parent block {}",
+                        object.block_index, block.block_index
+                    ),
+                );
+            }
+
+            if let Some(model_indices) = model_indices {
+                if !model_indices.contains(&object.model_table_index) {
+                    issues.push(
+                        location.as_str(),
+                        format!(
+                            "model_table_index {} not found in model table",
+                            object.model_table_index
+                        ),
+                    );
+                }
+            }
+
+            if let Some((first_location, _)) = objects.get(&object.object_index) {
+                issues.push(
+                    location.as_str(),
+                    format!(
+                        "object_index {} already used at {}",
+                        object.object_index, first_location
+                    ),
+                );
+            } else {
+                let object_extra_index = u32::try_from(object.object_extra_index).ok();
+                objects.insert(object.object_index, (location, object_extra_index));
+            }
+        }
+    }
+
+    issues.count(
+        "object_extras",
+        "object_extra_count",
+        loi.object_extra_count,
+        loi.object_extras.len(),
+    );
+
+    let mut extra_indices: HashMap<u32, u32> = HashMap::new();
+
+    for (extra_position, object_extra) in loi.object_extras.iter().enumerate() {
+        let location = format!(
+            "object_extras[{}] (object_extra_index {})",
+            extra_position, object_extra.object_extra_index
+        );
+
+        match objects.get(&object_extra.object_index) {
+            Some((_, Some(object_extra_index)))
+                if *object_extra_index != object_extra.object_extra_index =>
+            {
+                issues.push(
+                    location.as_str(),
+                    format!(
+                        "object {} refers to object_extra_index {} instead",
+                        object_extra.object_index, object_extra_index
+                    ),
+                );
+            }
+            Some((_, None)) => {
+                issues.push(
+                    location.as_str(),
+                    format!(
+                        "object {} has no object_extra_index",
+                        object_extra.object_index
+                    ),
+                );
+            }
+            Some(_) => {}
+            None => {
+                issues.push(
+                    location.as_str(),
+                    format!("object_index {} does not exist", object_extra.object_index),
+                );
+            }
+        }
+
+        if let Some(other_object_index) =
+            extra_indices.insert(object_extra.object_extra_index, object_extra.object_index)
+        {
+            issues.push(
+                location.as_str(),
+                format!(
+                    "object_extra_index also used by an extra for object {}",
+                    other_object_index
+                ),
+            );
+        }
+    }
+
+    // An object pointing at an extra that is missing or belongs to some
+    // other object
+    for (object_index, (location, object_extra_index)) in objects.iter() {
+        let object_extra_index = match object_extra_index {
+            Some(object_extra_index) => object_extra_index,
+            None => continue,
+        };
+
+        match extra_indices.get(object_extra_index) {
+            Some(extra_object_index) if extra_object_index != object_index => {
+                issues.push(
+                    location.as_str(),
+                    format!(
+                        "object_extra_index {} resolves to an extra for object {}",
+                        object_extra_index, extra_object_index
+                    ),
+                );
+            }
+            Some(_) => {}
+            None => {
+                issues.push(
+                    location.as_str(),
+                    format!(
+                        "object_extra_index {} does not resolve to an extra",
+                        object_extra_index
+                    ),
+                );
+            }
+        }
+    }
+
+    // The parser reads one entry per block for these
+    issues.count(
+        "unknown_objects_2",
+        "block_count",
+        loi.header.block_count,
+        loi.unknown_objects_2.len(),
+    );
+    issues.count(
+        "unknown_objects_4",
+        "block_count",
+        loi.header.block_count,
+        loi.unknown_objects_4.len(),
+    );
+    issues.count(
+        "unknown_objects_5",
+        "block_count",
+        loi.header.block_count,
+        loi.unknown_objects_5.len(),
+    );
+
+    for (i, unknown_object_2) in loi.unknown_objects_2.iter().enumerate() {
+        issues.count(
+            format!("unknown_objects_2[{}]", i),
+            "unknown_count",
+            unknown_object_2.unknown_count,
+            unknown_object_2.items.len(),
+        );
+    }
+
+    issues.count(
+        "unknown_objects_3",
+        "unknown_object_3_count",
+        loi.unknown_object_3_count,
+        loi.unknown_objects_3.len(),
+    );
+    for (i, unknown_object_3) in loi.unknown_objects_3.iter().enumerate() {
+        issues.count(
+            format!("unknown_objects_3[{}]", i),
+            "unknown_count",
+            unknown_object_3.unknown_count,
+            unknown_object_3.items.len(),
+        );
+    }
+
+    for (i, unknown_object_4) in loi.unknown_objects_4.iter().enumerate() {
+        issues.count(
+            format!("unknown_objects_4[{}]", i),
+            "unknown_count",
+            unknown_object_4.unknown_count,
+            unknown_object_4.items.len(),
+        );
+    }
+
+    for (i, unknown_object_5) in loi.unknown_objects_5.iter().enumerate() {
+        let location = format!("unknown_objects_5[{}]", i);
+
+        issues.count(
+            location.as_str(),
+            "object_count",
+            unknown_object_5.object_count,
+            unknown_object_5.object_indices.len(),
+        );
+
+        for object_index in unknown_object_5.object_indices.iter() {
+            if !objects.contains_key(object_index) {
+                issues.push(
+                    location.as_str(),
+                    format!("object_index {} does not exist", object_index),
+                );
+            }
+        }
+    }
+
+    issues
+}
@@ -1,18 +1,17 @@
 use std::time::Instant;
 
 use clap::Clap;
-
-mod agt;
-mod lbf;
-mod levelmodifier;
-mod lf;
-mod lof;
-mod loi;
-mod world;
+use slidetown_cli::{agt, lbf, levelmodifier, lf, lof, loi, output::OutputFormat, world};
 
 #[derive(Clap)]
 #[clap(version = env!("CARGO_PKG_VERSION"), author = "amPerl")]
 struct Opts {
+    #[clap(
+        long,
+        default_value = "text",
+        about = "output format of the info commands: text or json"
+    )]
+    format: OutputFormat,
     #[clap(subcommand, about = "archive type")]
     archive: Archive,
 }
@@ -35,23 +34,27 @@ enum Archive {
     LevelModifier(levelmodifier::LevelModifierOpts),
 }
 
-fn main() -> anyhow::Result<()> {
+fn main() {
     let opts: Opts = Opts::parse();
 
     let before_process = Instant::now();
 
     let result = match opts.archive {
-        Archive::Agt(agt_opts) => agt::process_agt(agt_opts),
-        Archive::Lf(lf_opts) => lf::process_lf(lf_opts),
-        Archive::Lbf(lbf_opts) => lbf::process_lbf(lbf_opts),
-        Archive::Lof(lof_opts) => lof::process_lof(lof_opts),
-        Archive::Loi(loi_opts) => loi::process_loi(loi_opts),
-        Archive::World(world_opts) => world::process_world(world_opts),
+        Archive::Agt(agt_opts) => agt::process_agt(agt_opts, opts.format),
+        Archive::Lf(lf_opts) => lf::process_lf(lf_opts, opts.format),
+        Archive::Lbf(lbf_opts) => lbf::process_lbf(lbf_opts, opts.format),
+        Archive::Lof(lof_opts) => lof::process_lof(lof_opts, opts.format),
+        Archive::Loi(loi_opts) => loi::process_loi(loi_opts, opts.format),
+        Archive::World(world_opts) => world::process_world(world_opts, opts.format),
         Archive::LevelModifier(levelmodifier_opts) => {
-            levelmodifier::process_levelmodifier(levelmodifier_opts)
+            levelmodifier::process_levelmodifier(levelmodifier_opts, opts.format)
         }
     };
 
-    println!("Done in {}ms", before_process.elapsed().as_millis());
-    result
+    eprintln!("Done in {}ms", before_process.elapsed().as_millis());
+
+    if let Err(error) = result {
+        eprintln!("Error: {:?}", error);
+        std::process::exit(slidetown_cli::error::exit_code(&error));
+    }
 }
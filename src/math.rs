@@ -0,0 +1,161 @@
+//! Small helpers for the rotation matrices stored in LOI placements.
+//!
+//! Matrices are kept in the same row order as the files store them, so
+//! `matrix[row][column]` corresponds to `rotation.row.column` in the parsed
+//! structures. Euler angles are in degrees and compose as `Rz * Ry * Rx`.
+
+pub type Vector3 = [f32; 3];
+pub type Matrix3 = [[f32; 3]; 3];
+pub type Quaternion = [f32; 4];
+
+pub const IDENTITY: Matrix3 = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+pub type RotationTuples = ((f32, f32, f32), (f32, f32, f32), (f32, f32, f32));
+
+pub fn matrix_from_tuples(rotation: &RotationTuples) -> Matrix3 {
+    [
+        [rotation.0 .0, rotation.0 .1, rotation.0 .2],
+        [rotation.1 .0, rotation.1 .1, rotation.1 .2],
+        [rotation.2 .0, rotation.2 .1, rotation.2 .2],
+    ]
+}
+
+pub fn matrix_to_tuples(matrix: &Matrix3) -> RotationTuples {
+    (
+        (matrix[0][0], matrix[0][1], matrix[0][2]),
+        (matrix[1][0], matrix[1][1], matrix[1][2]),
+        (matrix[2][0], matrix[2][1], matrix[2][2]),
+    )
+}
+
+pub fn matrix_from_euler(euler: Vector3) -> Matrix3 {
+    let (sx, cx) = euler[0].to_radians().sin_cos();
+    let (sy, cy) = euler[1].to_radians().sin_cos();
+    let (sz, cz) = euler[2].to_radians().sin_cos();
+
+    [
+        [cy * cz, sx * sy * cz - cx * sz, cx * sy * cz + sx * sz],
+        [cy * sz, sx * sy * sz + cx * cz, cx * sy * sz - sx * cz],
+        [-sy, sx * cy, cx * cy],
+    ]
+}
+
+pub fn euler_from_matrix(matrix: &Matrix3) -> Vector3 {
+    let sy = (-matrix[2][0]).clamp(-1.0, 1.0);
+    let y = sy.asin();
+
+    let (x, z) = if sy.abs() < 0.9999 {
+        (
+            matrix[2][1].atan2(matrix[2][2]),
+            matrix[1][0].atan2(matrix[0][0]),
+        )
+    } else {
+        // Gimbal lock, fold all of the remaining rotation into x
+        ((-matrix[1][2]).atan2(matrix[1][1]), 0.0)
+    };
+
+    [x.to_degrees(), y.to_degrees(), z.to_degrees()]
+}
+
+/// Returns the rotation as `[x, y, z, w]`.
+pub fn quaternion_from_matrix(matrix: &Matrix3) -> Quaternion {
+    let m = matrix;
+    let trace = m[0][0] + m[1][1] + m[2][2];
+
+    if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        [
+            (m[2][1] - m[1][2]) / s,
+            (m[0][2] - m[2][0]) / s,
+            (m[1][0] - m[0][1]) / s,
+            0.25 * s,
+        ]
+    } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+        let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+        [
+            0.25 * s,
+            (m[0][1] + m[1][0]) / s,
+            (m[0][2] + m[2][0]) / s,
+            (m[2][1] - m[1][2]) / s,
+        ]
+    } else if m[1][1] > m[2][2] {
+        let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+        [
+            (m[0][1] + m[1][0]) / s,
+            0.25 * s,
+            (m[1][2] + m[2][1]) / s,
+            (m[0][2] - m[2][0]) / s,
+        ]
+    } else {
+        let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+        [
+            (m[0][2] + m[2][0]) / s,
+            (m[1][2] + m[2][1]) / s,
+            0.25 * s,
+            (m[1][0] - m[0][1]) / s,
+        ]
+    }
+}
+
+/// Expects `[x, y, z, w]`, the quaternion is normalized first.
+pub fn matrix_from_quaternion(quaternion: Quaternion) -> Matrix3 {
+    let length = quaternion.iter().map(|c| c * c).sum::<f32>().sqrt();
+    let [x, y, z, w] = if length > 0.0 {
+        quaternion.map(|c| c / length)
+    } else {
+        [0.0, 0.0, 0.0, 1.0]
+    };
+
+    [
+        [
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y - z * w),
+            2.0 * (x * z + y * w),
+        ],
+        [
+            2.0 * (x * y + z * w),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z - x * w),
+        ],
+        [
+            2.0 * (x * z - y * w),
+            2.0 * (y * z + x * w),
+            1.0 - 2.0 * (x * x + y * y),
+        ],
+    ]
+}
+
+pub fn multiply(a: &Matrix3, b: &Matrix3) -> Matrix3 {
+    let mut result = [[0f32; 3]; 3];
+    for (row, result_row) in result.iter_mut().enumerate() {
+        for (col, value) in result_row.iter_mut().enumerate() {
+            *value = (0..3).map(|i| a[row][i] * b[i][col]).sum();
+        }
+    }
+    result
+}
+
+pub fn transform(matrix: &Matrix3, vector: Vector3) -> Vector3 {
+    let mut result = [0f32; 3];
+    for (row, value) in result.iter_mut().enumerate() {
+        *value = (0..3).map(|i| matrix[row][i] * vector[i]).sum();
+    }
+    result
+}
+
+/// Reflection across the plane perpendicular to the given axis.
+pub fn mirror_matrix(axis: usize) -> Matrix3 {
+    let mut matrix = IDENTITY;
+    matrix[axis][axis] = -1.0;
+    matrix
+}
+
+pub fn transpose(matrix: &Matrix3) -> Matrix3 {
+    let mut result = [[0f32; 3]; 3];
+    for (row, result_row) in result.iter_mut().enumerate() {
+        for (col, value) in result_row.iter_mut().enumerate() {
+            *value = matrix[col][row];
+        }
+    }
+    result
+}
@@ -0,0 +1,61 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::Serialize;
+
+/// How the info commands print their results. Progress and timing always go
+/// to stderr, so stdout only carries the result in either format.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => anyhow::bail!("unknown output format {:?}", s),
+        }
+    }
+}
+
+/// Prints `value` to stdout as a pretty printed JSON object.
+pub fn print_json<T: Serialize>(value: &T) -> anyhow::Result<()> {
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    serde_json::to_writer_pretty(&mut stdout, value)?;
+    std::io::Write::write_all(&mut stdout, b"\n")?;
+    Ok(())
+}
+
+/// Whether a file argument picks the JSON form over the binary or text one.
+pub fn is_json_path(path: &Path) -> bool {
+    matches!(path.extension(), Some(ext) if ext.eq_ignore_ascii_case("json"))
+}
+
+/// Turns a path stored in a game file into a relative path that stays inside
+/// an output directory: backslashes become separators, and root, drive, `.`
+/// and `..` components are dropped. Can come back empty.
+pub fn relative_output_path(stored_path: &str) -> PathBuf {
+    let normalized = stored_path.replace('\\', "/");
+
+    let mut path = PathBuf::new();
+    for component in Path::new(&normalized).components() {
+        if let Component::Normal(part) = component {
+            let part = part.to_string_lossy();
+            // Windows drive letters survive as normal components on Linux
+            if part.ends_with(':') {
+                continue;
+            }
+            path.push(part.as_ref());
+        }
+    }
+
+    path
+}
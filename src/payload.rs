@@ -0,0 +1,40 @@
+//! Nif payloads embedded in LF, LBF and LOF files.
+
+use std::{
+    fmt::Display,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+use crate::error::{Error, Result};
+
+/// Reads the `length` bytes at `offset`, `record` names the table entry for
+/// the error. The range is checked against the input first so a corrupt
+/// table fails instead of allocating whatever length it claims.
+pub fn read_payload<R, D>(input: &mut R, record: D, offset: u32, length: u32) -> Result<Vec<u8>>
+where
+    R: Read + Seek,
+    D: Display,
+{
+    let read = |input: &mut R| -> io::Result<Vec<u8>> {
+        let input_length = input.seek(SeekFrom::End(0))?;
+        if u64::from(offset) + u64::from(length) > input_length {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("ends past the end of the input ({} bytes)", input_length),
+            ));
+        }
+
+        input.seek(SeekFrom::Start(offset.into()))?;
+        let mut buffer = vec![0u8; length as usize];
+        input.read_exact(&mut buffer)?;
+        Ok(buffer)
+    };
+
+    read(input).map_err(|source| Error::Payload {
+        path: None,
+        record: record.to_string(),
+        offset,
+        length,
+        source,
+    })
+}
@@ -1,10 +1,21 @@
-use std::{
-    fs::File,
-    io::{Cursor, Read, Seek, SeekFrom, Write},
-    path::Path,
+use clap::Clap;
+use serde::Serialize;
+
+use crate::{
+    loi::selection::BlockRange,
+    output::{print_json, OutputFormat},
 };
 
-use clap::Clap;
+mod diff;
+mod health;
+mod layout;
+mod map;
+mod project;
+
+use diff::DiffOpts;
+use health::HealthOpts;
+use layout::{LayoutOpts, WorldFile};
+use map::MapOpts;
 
 #[derive(Clap)]
 pub struct WorldOpts {
@@ -16,76 +27,79 @@ pub struct WorldOpts {
 enum Command {
     #[clap(about = "display info about world")]
     Info(InfoOpts),
-    #[clap(about = "print object density map for world")]
-    Map(InfoOpts),
+    #[clap(about = "print or export object density map for world")]
+    Map(MapOpts),
+    #[clap(about = "export preview gltf with terrain, block objects and placed objects")]
+    Gltf(GltfOpts),
+    #[clap(about = "compare the files of two world directories")]
+    Diff(DiffOpts),
+    #[clap(about = "unpack all world files into one project directory")]
+    Unpack(project::UnpackOpts),
+    #[clap(about = "check and pack a project directory back into world files")]
+    Pack(project::PackOpts),
 }
 
 #[derive(Clap)]
 struct InfoOpts {
-    #[clap(short, long, about = "input directory")]
-    input_path: String,
+    #[clap(flatten)]
+    layout: LayoutOpts,
+    #[clap(flatten)]
+    health: HealthOpts,
 }
 
-fn try_parse_nifs<I, T>(file: &mut File, named_offsets: I) -> anyhow::Result<()>
-where
-    I: Iterator<Item = (T, u32, u32)>,
-    T: std::fmt::Debug,
-{
-    let mut nif_errors = Vec::new();
-    let mut count = 0;
-
-    for (idx, pos, len) in named_offsets {
-        count += 1;
-
-        file.seek(SeekFrom::Start(pos as u64))?;
-        let mut buf = vec![0u8; len as usize];
-        file.read_exact(&mut buf)?;
-
-        let mut nif_cursor = Cursor::new(buf);
-
-        if let Err(e) = nif::Nif::parse(&mut nif_cursor) {
-            println!("Failed to parse nif id {:?} - {:#?}", idx, e);
-            nif_errors.push(e);
-
-            // dump for debug
-            file.seek(SeekFrom::Start(pos as u64))
-                .expect("failed to seek for dump");
-            let mut buf = vec![0u8; len as usize];
-            file.read_exact(&mut buf).expect("failed to read for dump");
-            let mut dump_file =
-                std::fs::File::create("dump.nif").expect("failed to create file for dump");
-            dump_file.write_all(&buf).expect("failed to write for dump");
-            panic!("dumped file");
-        }
-    }
+/// `world info --format json` output. `header_*` fields are the counts
+/// stored in the file headers, the others are counted from the tables.
+#[derive(Serialize)]
+struct Info {
+    lf: LfInfo,
+    lbf: LbfInfo,
+    lof: LofInfo,
+    loi: LoiInfo,
+    /// Nif parse results per file, in the same shape as `--report-path`
+    nifs: Vec<health::FileReport>,
+}
 
-    let parsed_nifs = count - nif_errors.len();
+#[derive(Serialize)]
+struct LfInfo {
+    size_x: u32,
+    size_y: u32,
+    size_idx: u32,
+    header_block_count: u32,
+    block_count: usize,
+}
 
-    println!(
-        "[try_parse_nifs] Parsed {} nifs of {} ({}%)",
-        parsed_nifs,
-        count,
-        (parsed_nifs as f32) / (count as f32) * 100.0
-    );
+#[derive(Serialize)]
+struct LbfInfo {
+    header_block_count: u32,
+    block_count: usize,
+    header_block_object_count: u32,
+    block_object_count: u32,
+}
 
-    Ok(())
+#[derive(Serialize)]
+struct LofInfo {
+    header_model_count: u32,
+    model_count: usize,
 }
 
-fn process_info(info_opts: InfoOpts) -> anyhow::Result<()> {
-    let input_path = Path::new(&info_opts.input_path);
+#[derive(Serialize)]
+struct LoiInfo {
+    header_block_count: u32,
+    block_count: usize,
+    /// Blocks with at least one object
+    blocks_with_objects: usize,
+    object_count: u32,
+}
 
-    let mut lf_file = File::open(input_path.join("terrain0.lf"))?;
-    let lf = slidetown::parsers::lf::Lf::parse(&mut lf_file)?;
+fn process_info(info_opts: InfoOpts, format: OutputFormat) -> anyhow::Result<()> {
+    let layout = info_opts.layout.resolve()?;
+    let mut nif_health = info_opts.health.resolve()?;
 
-    println!(
-        "[lf] Terrain dimensions: {:?}",
-        (lf.header.size_x, lf.header.size_y, lf.header.size_idx)
-    );
-    println!("[lf] Blocks in terrain header: {}", lf.header.block_count);
-    println!("[lf] Blocks in terrain: {}", lf.blocks.len());
+    let (lf, mut lf_file) = layout.parse(WorldFile::Lf, slidetown::parsers::lf::Lf::parse)?;
 
-    println!("[lf] Parsing nifs..");
-    try_parse_nifs(
+    eprintln!("[lf] Parsing nifs..");
+    nif_health.check(
+        "lf",
         &mut lf_file,
         lf.blocks
             .iter()
@@ -93,28 +107,11 @@ fn process_info(info_opts: InfoOpts) -> anyhow::Result<()> {
             .map(|b| (b.index, b.file_offset, b.file_length)),
     )?;
 
-    let mut lbf_file = File::open(input_path.join("blockObj0.lbf"))?;
-    let lbf = slidetown::parsers::lbf::Lbf::parse(&mut lbf_file)?;
+    let (lbf, mut lbf_file) = layout.parse(WorldFile::Lbf, slidetown::parsers::lbf::Lbf::parse)?;
 
-    println!(
-        "[lbf] Blocks in blockObj header: {}",
-        lbf.header.block_count
-    );
-    println!("[lbf] Blocks in blockObj: {}", lbf.blocks.len());
-    println!(
-        "[lbf] Block objects in blockObj header: {}",
-        lbf.header.block_object_count
-    );
-    println!(
-        "[lbf] Block objects in blockObj: {}",
-        lbf.blocks
-            .iter()
-            .map(|b: &slidetown::parsers::lbf::Block| b.object_count)
-            .sum::<u32>()
-    );
-
-    println!("[lbf] Parsing nifs..");
-    try_parse_nifs(
+    eprintln!("[lbf] Parsing nifs..");
+    nif_health.check(
+        "lbf",
         &mut lbf_file,
         lbf.blocks.iter().flat_map(|b| {
             b.objects
@@ -123,96 +120,160 @@ fn process_info(info_opts: InfoOpts) -> anyhow::Result<()> {
         }),
     )?;
 
-    let mut lof_file = File::open(input_path.join("modeltable0.lof"))?;
-    let lof = slidetown::parsers::lof::Lof::parse(&mut lof_file)?;
-
-    println!("[lof] Models in table header: {}", lof.header.model_count);
-    println!("[lof] Models in table: {}", lof.models.len());
+    let (lof, mut lof_file) = layout.parse(WorldFile::Lof, slidetown::parsers::lof::Lof::parse)?;
 
-    println!("[lof] Parsing nifs..");
-    try_parse_nifs(
+    eprintln!("[lof] Parsing nifs..");
+    nif_health.check(
+        "lof",
         &mut lof_file,
         lof.models
             .iter()
             .map(|m| (&m.file_name, m.file_offset, m.file_length)),
     )?;
 
-    let mut loi_file = File::open(input_path.join("Main\\object0.loI"))?;
-    let loi = slidetown::parsers::loi::Loi::parse(&mut loi_file)?;
+    let (loi, _) = layout.parse(WorldFile::Loi, slidetown::parsers::loi::Loi::parse)?;
+
+    let info = Info {
+        lf: LfInfo {
+            size_x: lf.header.size_x,
+            size_y: lf.header.size_y,
+            size_idx: lf.header.size_idx,
+            header_block_count: lf.header.block_count,
+            block_count: lf.blocks.len(),
+        },
+        lbf: LbfInfo {
+            header_block_count: lbf.header.block_count,
+            block_count: lbf.blocks.len(),
+            header_block_object_count: lbf.header.block_object_count,
+            block_object_count: lbf.blocks.iter().map(|b| b.object_count).sum::<u32>(),
+        },
+        lof: LofInfo {
+            header_model_count: lof.header.model_count,
+            model_count: lof.models.len(),
+        },
+        loi: LoiInfo {
+            header_block_count: loi.header.block_count,
+            block_count: loi.blocks.len(),
+            blocks_with_objects: loi.blocks.iter().filter(|b| b.object_count > 0).count(),
+            object_count: loi.blocks.iter().map(|b| b.object_count).sum::<u32>(),
+        },
+        nifs: nif_health.finish()?,
+    };
+
+    match format {
+        OutputFormat::Json => print_json(&info)?,
+        OutputFormat::Text => print_info(&info),
+    }
+
+    Ok(())
+}
+
+fn print_info(info: &Info) {
+    println!(
+        "[lf] Terrain dimensions: {:?}",
+        (info.lf.size_x, info.lf.size_y, info.lf.size_idx)
+    );
+    println!(
+        "[lf] Blocks in terrain header: {}",
+        info.lf.header_block_count
+    );
+    println!("[lf] Blocks in terrain: {}", info.lf.block_count);
+
+    println!(
+        "[lbf] Blocks in blockObj header: {}",
+        info.lbf.header_block_count
+    );
+    println!("[lbf] Blocks in blockObj: {}", info.lbf.block_count);
+    println!(
+        "[lbf] Block objects in blockObj header: {}",
+        info.lbf.header_block_object_count
+    );
+    println!(
+        "[lbf] Block objects in blockObj: {}",
+        info.lbf.block_object_count
+    );
+
+    println!(
+        "[lof] Models in table header: {}",
+        info.lof.header_model_count
+    );
+    println!("[lof] Models in table: {}", info.lof.model_count);
 
     println!(
         "[loi] Blocks in object index header: {}",
-        loi.header.block_count
+        info.loi.header_block_count
     );
-    println!("[loi] Blocks in object index: {}", loi.blocks.len());
+    println!("[loi] Blocks in object index: {}", info.loi.block_count);
     println!(
         "[loi] Blocks with 1 or more objects: {}",
-        loi.blocks.iter().filter(|b| b.object_count > 0).count()
+        info.loi.blocks_with_objects
     );
     println!(
         "[loi] Objects in all blocks combined: {}",
-        loi.blocks.iter().map(|b| b.object_count).sum::<u32>()
+        info.loi.object_count
     );
 
-    Ok(())
+    for report in info.nifs.iter() {
+        println!("[{}] {}", report.file, report.summary());
+    }
 }
 
-fn process_map(info_opts: InfoOpts) -> anyhow::Result<()> {
-    let input_path = Path::new(&info_opts.input_path);
+#[derive(Clap)]
+struct GltfOpts {
+    #[clap(flatten)]
+    layout: LayoutOpts,
+    #[clap(short, long, about = "output file")]
+    output_path: String,
+    #[clap(
+        long,
+        about = "block index or range START-END to export, can be repeated"
+    )]
+    blocks: Vec<BlockRange>,
+}
 
-    let lf = {
-        let mut file = File::open(input_path.join("terrain0.lf"))?;
-        slidetown::parsers::lf::Lf::parse(&mut file)?
-    };
+fn process_gltf(gltf_opts: GltfOpts) -> anyhow::Result<()> {
+    let layout = gltf_opts.layout.resolve()?;
+    let blocks = gltf_opts.blocks;
+    let in_region =
+        |block_index: u32| blocks.is_empty() || blocks.iter().any(|r| r.contains(block_index));
 
-    let loi = {
-        let mut file = File::open(input_path.join("Main\\object0.loI"))?;
-        slidetown::parsers::loi::Loi::parse(&mut file)?
-    };
+    let mut gltf = nif::gltf::Gltf::new();
 
-    println!("Object count by block:");
-    print!("    ");
-    for x in 0..lf.header.size_x {
-        match x % 10 {
-            0 => print!("{}", x / 10),
-            _ => print!(" "),
-        }
-    }
-    println!();
-    print!("    ");
-    for x in 0..lf.header.size_x {
-        print!("{}", x % 10);
-    }
-    println!();
-    for y in 0..lf.header.size_y {
-        let row_offset = y * lf.header.size_x;
-
-        let row_counts: Vec<u32> = (row_offset..row_offset + lf.header.size_x)
-            .map(|i| loi.blocks.get(i as usize).map_or(0, |b| b.object_count))
-            .collect();
-
-        if row_counts.iter().sum::<u32>() == 0 {
-            continue;
-        }
-
-        print!("{:03} ", y);
-        for x in 0..lf.header.size_x {
-            let object_count = row_counts.get(x as usize).unwrap();
-            match object_count {
-                0 => print!("_"),
-                1..=9 => print!("{}", object_count),
-                _ => print!("+"),
-            };
-        }
-        println!();
-    }
+    eprintln!("[lf] Adding terrain..");
+    let (lf, mut lf_file) = layout.parse(WorldFile::Lf, slidetown::parsers::lf::Lf::parse)?;
+    crate::lf::visit_gltf_blocks(&mut gltf, &mut lf_file, &lf, |block| in_region(block.index))?;
+
+    eprintln!("[lbf] Adding block objects..");
+    let (lbf, mut lbf_file) = layout.parse(WorldFile::Lbf, slidetown::parsers::lbf::Lbf::parse)?;
+    crate::lbf::visit_gltf_block_objects(&mut gltf, &mut lbf_file, &lbf, |block_object| {
+        in_region(block_object.index)
+    })?;
+
+    eprintln!("[lof] Adding models..");
+    let lof_path = layout.path(WorldFile::Lof)?;
+    let model_indices = crate::lof::visit_gltf_models(&mut gltf, &lof_path, None)?;
+
+    eprintln!("[loi] Adding placed objects..");
+    let (loi, _) = layout.parse(WorldFile::Loi, slidetown::parsers::loi::Loi::parse)?;
+    let instance_tags =
+        crate::loi::visit_gltf_instances(&mut gltf, &loi, &model_indices, |block| {
+            in_region(block.block_index)
+        });
+
+    let gltf_path = std::path::PathBuf::from(gltf_opts.output_path);
+    gltf.write_to_files(gltf_path.clone())?;
+    crate::loi::tag_instance_nodes(&gltf_path, &instance_tags)?;
 
     Ok(())
 }
 
-pub fn process_world(world_opts: WorldOpts) -> anyhow::Result<()> {
+pub fn process_world(world_opts: WorldOpts, format: OutputFormat) -> anyhow::Result<()> {
     match world_opts.cmd {
-        Command::Info(info_opts) => process_info(info_opts),
-        Command::Map(info_opts) => process_map(info_opts),
+        Command::Info(info_opts) => process_info(info_opts, format),
+        Command::Map(map_opts) => map::process_map(map_opts),
+        Command::Gltf(gltf_opts) => process_gltf(gltf_opts),
+        Command::Diff(diff_opts) => diff::process_diff(diff_opts),
+        Command::Unpack(unpack_opts) => project::process_unpack(unpack_opts),
+        Command::Pack(pack_opts) => project::process_pack(pack_opts),
     }
 }
@@ -0,0 +1,488 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use clap::Clap;
+use serde::Serialize;
+use slidetown::parsers::{lbf, lf, lof, loi};
+
+use super::layout::{WorldFile, WorldLayout};
+use crate::{error::PathContext, payload::read_payload};
+
+#[derive(Clap)]
+pub struct DiffOpts {
+    #[clap(about = "old world directory")]
+    old_path: String,
+    #[clap(about = "new world directory")]
+    new_path: String,
+    #[clap(
+        long,
+        about = "area index used in the default file names of both worlds"
+    )]
+    area: Option<u32>,
+    #[clap(short, long, about = "write the change list as json to this file")]
+    output_path: Option<String>,
+    #[clap(long, about = "print every change, not just the summary")]
+    verbose: bool,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Change {
+    LfBlockAdded {
+        index: u32,
+    },
+    LfBlockRemoved {
+        index: u32,
+    },
+    LfBlockChanged {
+        index: u32,
+        old_length: u32,
+        new_length: u32,
+    },
+    LbfObjectAdded {
+        block_index: u32,
+        unk: u32,
+    },
+    LbfObjectRemoved {
+        block_index: u32,
+        unk: u32,
+    },
+    LbfObjectChanged {
+        block_index: u32,
+        unk: u32,
+    },
+    LofModelAdded {
+        index: u32,
+        name: String,
+    },
+    LofModelRemoved {
+        index: u32,
+        name: String,
+    },
+    LofModelRenamed {
+        index: u32,
+        old_name: String,
+        new_name: String,
+        old_file_name: String,
+        new_file_name: String,
+    },
+    LofModelChanged {
+        index: u32,
+        name: String,
+    },
+    LoiObjectAdded {
+        object_index: u32,
+        block_index: u32,
+        model_table_index: u32,
+    },
+    LoiObjectRemoved {
+        object_index: u32,
+        block_index: u32,
+        model_table_index: u32,
+    },
+    LoiObjectMoved {
+        object_index: u32,
+        old_block_index: u32,
+        new_block_index: u32,
+        delta: (f32, f32, f32),
+    },
+    LoiObjectModelChanged {
+        object_index: u32,
+        old_model_table_index: u32,
+        new_model_table_index: u32,
+    },
+}
+
+impl Change {
+    /// Same as the `kind` tag in the json output.
+    fn kind(&self) -> &'static str {
+        match self {
+            Change::LfBlockAdded { .. } => "lf_block_added",
+            Change::LfBlockRemoved { .. } => "lf_block_removed",
+            Change::LfBlockChanged { .. } => "lf_block_changed",
+            Change::LbfObjectAdded { .. } => "lbf_object_added",
+            Change::LbfObjectRemoved { .. } => "lbf_object_removed",
+            Change::LbfObjectChanged { .. } => "lbf_object_changed",
+            Change::LofModelAdded { .. } => "lof_model_added",
+            Change::LofModelRemoved { .. } => "lof_model_removed",
+            Change::LofModelRenamed { .. } => "lof_model_renamed",
+            Change::LofModelChanged { .. } => "lof_model_changed",
+            Change::LoiObjectAdded { .. } => "loi_object_added",
+            Change::LoiObjectRemoved { .. } => "loi_object_removed",
+            Change::LoiObjectMoved { .. } => "loi_object_moved",
+            Change::LoiObjectModelChanged { .. } => "loi_object_model_changed",
+        }
+    }
+
+    fn file(&self) -> &'static str {
+        self.kind().split('_').next().unwrap_or_default()
+    }
+
+    fn description(&self) -> String {
+        match self {
+            Change::LfBlockAdded { index } => format!("block {} added", index),
+            Change::LfBlockRemoved { index } => format!("block {} removed", index),
+            Change::LfBlockChanged {
+                index,
+                old_length,
+                new_length,
+            } => format!(
+                "block {} nif changed ({} -> {} bytes)",
+                index, old_length, new_length
+            ),
+            Change::LbfObjectAdded { block_index, unk } => {
+                format!("block {} object {} added", block_index, unk)
+            }
+            Change::LbfObjectRemoved { block_index, unk } => {
+                format!("block {} object {} removed", block_index, unk)
+            }
+            Change::LbfObjectChanged { block_index, unk } => {
+                format!("block {} object {} nif changed", block_index, unk)
+            }
+            Change::LofModelAdded { index, name } => format!("model {} {:?} added", index, name),
+            Change::LofModelRemoved { index, name } => {
+                format!("model {} {:?} removed", index, name)
+            }
+            Change::LofModelRenamed {
+                index,
+                old_name,
+                new_name,
+                old_file_name,
+                new_file_name,
+            } => format!(
+                "model {} renamed {:?} ({:?}) -> {:?} ({:?})",
+                index, old_name, old_file_name, new_name, new_file_name
+            ),
+            Change::LofModelChanged { index, name } => {
+                format!("model {} {:?} nif changed", index, name)
+            }
+            Change::LoiObjectAdded {
+                object_index,
+                block_index,
+                model_table_index,
+            } => format!(
+                "object {} (model {}) added in block {}",
+                object_index, model_table_index, block_index
+            ),
+            Change::LoiObjectRemoved {
+                object_index,
+                block_index,
+                model_table_index,
+            } => format!(
+                "object {} (model {}) removed from block {}",
+                object_index, model_table_index, block_index
+            ),
+            Change::LoiObjectMoved {
+                object_index,
+                old_block_index,
+                new_block_index,
+                delta,
+            } => format!(
+                "object {} moved by {:?} (block {} -> {})",
+                object_index, delta, old_block_index, new_block_index
+            ),
+            Change::LoiObjectModelChanged {
+                object_index,
+                old_model_table_index,
+                new_model_table_index,
+            } => format!(
+                "object {} model {} -> {}",
+                object_index, old_model_table_index, new_model_table_index
+            ),
+        }
+    }
+}
+
+/// One side of the comparison, the files are kept open to read nif bytes.
+struct World {
+    lf_file: File,
+    lf: lf::Lf,
+    lbf_file: File,
+    lbf: lbf::Lbf,
+    lof_file: File,
+    lof: lof::Lof,
+    loi: loi::Loi,
+}
+
+impl World {
+    fn open(path: &str, area: Option<u32>) -> anyhow::Result<Self> {
+        let layout = WorldLayout::new(PathBuf::from(path), area, Default::default())?;
+
+        let (lf, lf_file) = layout.parse(WorldFile::Lf, lf::Lf::parse)?;
+        let (lbf, lbf_file) = layout.parse(WorldFile::Lbf, lbf::Lbf::parse)?;
+        let (lof, lof_file) = layout.parse(WorldFile::Lof, lof::Lof::parse)?;
+        let (loi, _) = layout.parse(WorldFile::Loi, loi::Loi::parse)?;
+
+        Ok(World {
+            lf_file,
+            lf,
+            lbf_file,
+            lbf,
+            lof_file,
+            lof,
+            loi,
+        })
+    }
+}
+
+/// Walks the union of two keyed maps in key order.
+fn union_keys<K: Ord + Copy, A, B>(old: &BTreeMap<K, A>, new: &BTreeMap<K, B>) -> BTreeSet<K> {
+    old.keys().chain(new.keys()).copied().collect()
+}
+
+fn diff_lf(old: &mut World, new: &mut World, changes: &mut Vec<Change>) -> anyhow::Result<()> {
+    let old_blocks: BTreeMap<u32, &lf::Block> = old
+        .lf
+        .blocks
+        .iter()
+        .filter(|b| b.file_length > 0)
+        .map(|b| (b.index, b))
+        .collect();
+    let new_blocks: BTreeMap<u32, &lf::Block> = new
+        .lf
+        .blocks
+        .iter()
+        .filter(|b| b.file_length > 0)
+        .map(|b| (b.index, b))
+        .collect();
+
+    for index in union_keys(&old_blocks, &new_blocks) {
+        match (old_blocks.get(&index), new_blocks.get(&index)) {
+            (Some(old_block), Some(new_block)) => {
+                let old_bytes = read_payload(
+                    &mut old.lf_file,
+                    format_args!("block {}", index),
+                    old_block.file_offset,
+                    old_block.file_length,
+                )?;
+                let new_bytes = read_payload(
+                    &mut new.lf_file,
+                    format_args!("block {}", index),
+                    new_block.file_offset,
+                    new_block.file_length,
+                )?;
+                if old_bytes != new_bytes {
+                    changes.push(Change::LfBlockChanged {
+                        index,
+                        old_length: old_block.file_length,
+                        new_length: new_block.file_length,
+                    });
+                }
+            }
+            (Some(_), None) => changes.push(Change::LfBlockRemoved { index }),
+            (None, Some(_)) => changes.push(Change::LfBlockAdded { index }),
+            (None, None) => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn diff_lbf(old: &mut World, new: &mut World, changes: &mut Vec<Change>) -> anyhow::Result<()> {
+    let key_objects = |lbf: &lbf::Lbf| -> BTreeMap<(u32, u32), (u32, u32)> {
+        lbf.blocks
+            .iter()
+            .flat_map(|b| b.objects.iter())
+            .map(|bo| ((bo.index, bo.unk), (bo.file_offset, bo.file_length)))
+            .collect()
+    };
+    let old_objects = key_objects(&old.lbf);
+    let new_objects = key_objects(&new.lbf);
+
+    for (block_index, unk) in union_keys(&old_objects, &new_objects) {
+        let key = (block_index, unk);
+        match (old_objects.get(&key), new_objects.get(&key)) {
+            (Some(&(old_offset, old_length)), Some(&(new_offset, new_length))) => {
+                let old_bytes = read_payload(
+                    &mut old.lbf_file,
+                    format_args!("block object {} ({})", block_index, unk),
+                    old_offset,
+                    old_length,
+                )?;
+                let new_bytes = read_payload(
+                    &mut new.lbf_file,
+                    format_args!("block object {} ({})", block_index, unk),
+                    new_offset,
+                    new_length,
+                )?;
+                if old_bytes != new_bytes {
+                    changes.push(Change::LbfObjectChanged { block_index, unk });
+                }
+            }
+            (Some(_), None) => changes.push(Change::LbfObjectRemoved { block_index, unk }),
+            (None, Some(_)) => changes.push(Change::LbfObjectAdded { block_index, unk }),
+            (None, None) => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn diff_lof(old: &mut World, new: &mut World, changes: &mut Vec<Change>) -> anyhow::Result<()> {
+    let old_models: BTreeMap<u32, &lof::Model> =
+        old.lof.models.iter().map(|m| (m.index, m)).collect();
+    let new_models: BTreeMap<u32, &lof::Model> =
+        new.lof.models.iter().map(|m| (m.index, m)).collect();
+
+    for index in union_keys(&old_models, &new_models) {
+        match (old_models.get(&index), new_models.get(&index)) {
+            (Some(old_model), Some(new_model)) => {
+                if old_model.name != new_model.name || old_model.file_name != new_model.file_name {
+                    changes.push(Change::LofModelRenamed {
+                        index,
+                        old_name: old_model.name.clone(),
+                        new_name: new_model.name.clone(),
+                        old_file_name: old_model.file_name.clone(),
+                        new_file_name: new_model.file_name.clone(),
+                    });
+                }
+
+                let old_bytes = read_payload(
+                    &mut old.lof_file,
+                    format_args!("model {}", index),
+                    old_model.file_offset,
+                    old_model.file_length,
+                )?;
+                let new_bytes = read_payload(
+                    &mut new.lof_file,
+                    format_args!("model {}", index),
+                    new_model.file_offset,
+                    new_model.file_length,
+                )?;
+                if old_bytes != new_bytes {
+                    changes.push(Change::LofModelChanged {
+                        index,
+                        name: new_model.name.clone(),
+                    });
+                }
+            }
+            (Some(old_model), None) => changes.push(Change::LofModelRemoved {
+                index,
+                name: old_model.name.clone(),
+            }),
+            (None, Some(new_model)) => changes.push(Change::LofModelAdded {
+                index,
+                name: new_model.name.clone(),
+            }),
+            (None, None) => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// block_index, model_table_index, position
+type ObjectPlacement = (u32, u32, (f32, f32, f32));
+
+fn diff_loi(old: &World, new: &World, changes: &mut Vec<Change>) {
+    let key_objects = |loi: &loi::Loi| -> BTreeMap<u32, ObjectPlacement> {
+        loi.blocks
+            .iter()
+            .flat_map(|b| {
+                b.objects.iter().map(move |o| {
+                    (
+                        o.object_index,
+                        (b.block_index, o.model_table_index, o.position),
+                    )
+                })
+            })
+            .collect()
+    };
+    let old_objects = key_objects(&old.loi);
+    let new_objects = key_objects(&new.loi);
+
+    for object_index in union_keys(&old_objects, &new_objects) {
+        match (
+            old_objects.get(&object_index),
+            new_objects.get(&object_index),
+        ) {
+            (Some(&(old_block, old_model, old_pos)), Some(&(new_block, new_model, new_pos))) => {
+                if old_pos != new_pos || old_block != new_block {
+                    changes.push(Change::LoiObjectMoved {
+                        object_index,
+                        old_block_index: old_block,
+                        new_block_index: new_block,
+                        delta: (
+                            new_pos.0 - old_pos.0,
+                            new_pos.1 - old_pos.1,
+                            new_pos.2 - old_pos.2,
+                        ),
+                    });
+                }
+                if old_model != new_model {
+                    changes.push(Change::LoiObjectModelChanged {
+                        object_index,
+                        old_model_table_index: old_model,
+                        new_model_table_index: new_model,
+                    });
+                }
+            }
+            (Some(&(block_index, model_table_index, _)), None) => {
+                changes.push(Change::LoiObjectRemoved {
+                    object_index,
+                    block_index,
+                    model_table_index,
+                })
+            }
+            (None, Some(&(block_index, model_table_index, _))) => {
+                changes.push(Change::LoiObjectAdded {
+                    object_index,
+                    block_index,
+                    model_table_index,
+                })
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+pub fn process_diff(diff_opts: DiffOpts) -> anyhow::Result<()> {
+    let mut old = World::open(&diff_opts.old_path, diff_opts.area)?;
+    let mut new = World::open(&diff_opts.new_path, diff_opts.area)?;
+
+    let mut changes = Vec::new();
+    diff_lf(&mut old, &mut new, &mut changes)?;
+    diff_lbf(&mut old, &mut new, &mut changes)?;
+    diff_lof(&mut old, &mut new, &mut changes)?;
+    diff_loi(&old, &new, &mut changes);
+
+    // file -> change kind -> count
+    let mut summary: BTreeMap<&str, BTreeMap<&str, usize>> = BTreeMap::new();
+    for change in changes.iter() {
+        *summary
+            .entry(change.file())
+            .or_default()
+            .entry(change.kind())
+            .or_default() += 1;
+    }
+
+    for file in ["lf", "lbf", "lof", "loi"] {
+        match summary.get(file) {
+            Some(kinds) => {
+                let counts: Vec<String> = kinds
+                    .iter()
+                    .map(|(kind, count)| format!("{} {}", count, kind))
+                    .collect();
+                println!("[{}] {}", file, counts.join(", "));
+            }
+            None => println!("[{}] no changes", file),
+        }
+
+        if diff_opts.verbose {
+            for change in changes.iter().filter(|c| c.file() == file) {
+                println!("  {}", change.description());
+            }
+        }
+    }
+
+    if let Some(output_path) = diff_opts.output_path {
+        let output_file = File::create(&output_path).with_path(Path::new(&output_path))?;
+        serde_json::to_writer_pretty(output_file, &changes)?;
+        eprintln!("Wrote {} changes to {}", changes.len(), output_path);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,224 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Cursor, Write},
+    path::PathBuf,
+};
+
+use clap::Clap;
+use serde::Serialize;
+
+use crate::{error::PathContext, output::is_json_path, payload::read_payload};
+
+#[derive(Clap)]
+pub struct HealthOpts {
+    #[clap(long, about = "directory to write nifs that fail to parse into")]
+    dump_path: Option<String>,
+    #[clap(
+        long,
+        about = "write a nif report to this file, json if it ends in .json, text otherwise"
+    )]
+    report_path: Option<String>,
+}
+
+#[derive(Serialize)]
+struct NifFailure {
+    id: String,
+    offset: u32,
+    length: u32,
+    error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dump_path: Option<String>,
+}
+
+/// Parse results for the nifs embedded in one world file.
+#[derive(Serialize)]
+pub struct FileReport {
+    pub file: String,
+    parsed: usize,
+    total: usize,
+    /// Failures grouped by the kind of parse error
+    failures: BTreeMap<String, Vec<NifFailure>>,
+}
+
+impl FileReport {
+    pub fn summary(&self) -> String {
+        if self.total == 0 {
+            return "No nifs to parse".to_string();
+        }
+
+        format!(
+            "Parsed {} nifs of {} ({}%)",
+            self.parsed,
+            self.total,
+            (self.parsed as f32) / (self.total as f32) * 100.0
+        )
+    }
+}
+
+/// Collects parse failures of every nif across the world files instead of
+/// stopping at the first one.
+pub struct NifHealth {
+    dump_path: Option<PathBuf>,
+    report_path: Option<PathBuf>,
+    reports: Vec<FileReport>,
+}
+
+impl HealthOpts {
+    pub fn resolve(&self) -> anyhow::Result<NifHealth> {
+        let dump_path = self.dump_path.as_ref().map(PathBuf::from);
+        if let Some(dump_path) = &dump_path {
+            std::fs::create_dir_all(dump_path)?;
+        }
+
+        Ok(NifHealth {
+            dump_path,
+            report_path: self.report_path.as_ref().map(PathBuf::from),
+            reports: Vec::new(),
+        })
+    }
+}
+
+/// Variant or type name at the start of a debug formatted error.
+fn error_kind(error: &str) -> String {
+    let kind: String = error
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == ':')
+        .collect();
+    if kind.is_empty() {
+        "Unknown".to_string()
+    } else {
+        kind
+    }
+}
+
+/// Ids are not always unique within a file, so the offset is kept in the name.
+fn dump_file_name(file: &str, id: &str, offset: u32) -> String {
+    let id: String = id
+        .trim_matches('"')
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect();
+    let id = id.strip_suffix("_nif").unwrap_or(&id);
+    format!("{}_{}_{}.nif", file, id, offset)
+}
+
+impl NifHealth {
+    pub fn check<I, T>(
+        &mut self,
+        name: &str,
+        file: &mut File,
+        named_offsets: I,
+    ) -> anyhow::Result<()>
+    where
+        I: Iterator<Item = (T, u32, u32)>,
+        T: std::fmt::Debug,
+    {
+        let mut report = FileReport {
+            file: name.to_string(),
+            parsed: 0,
+            total: 0,
+            failures: BTreeMap::new(),
+        };
+
+        for (idx, pos, len) in named_offsets {
+            report.total += 1;
+
+            // A table entry pointing outside the file is recorded like any
+            // other failure, there is just nothing to dump
+            let (kind, error, nif_buffer) =
+                match read_payload(file, format_args!("{} nif {:?}", name, idx), pos, len) {
+                    Ok(buf) => {
+                        let mut nif_cursor = Cursor::new(buf);
+                        match nif::Nif::parse(&mut nif_cursor) {
+                            Ok(_) => {
+                                report.parsed += 1;
+                                continue;
+                            }
+                            Err(e) => {
+                                let error = format!("{:?}", e);
+                                (error_kind(&error), error, Some(nif_cursor.into_inner()))
+                            }
+                        }
+                    }
+                    Err(e) => ("OutOfRange".to_string(), e.to_string(), None),
+                };
+
+            let id = format!("{:?}", idx);
+            eprintln!("[{}] Failed to parse nif id {} - {}", name, id, error);
+
+            let dump_path = match (&self.dump_path, &nif_buffer) {
+                (Some(dump_path), Some(nif_buffer)) => {
+                    let path = dump_path.join(dump_file_name(name, &id, pos));
+                    File::create(&path)
+                        .and_then(|mut dump_file| dump_file.write_all(nif_buffer))
+                        .with_path(&path)?;
+                    Some(path.to_string_lossy().into_owned())
+                }
+                _ => None,
+            };
+
+            report.failures.entry(kind).or_default().push(NifFailure {
+                id,
+                offset: pos,
+                length: len,
+                error,
+                dump_path,
+            });
+        }
+
+        self.reports.push(report);
+
+        Ok(())
+    }
+
+    /// Writes the report if one was requested and hands back the results.
+    pub fn finish(self) -> anyhow::Result<Vec<FileReport>> {
+        let report_path = match &self.report_path {
+            Some(report_path) => report_path,
+            None => return Ok(self.reports),
+        };
+
+        let mut report_file = File::create(report_path).with_path(report_path)?;
+
+        if is_json_path(report_path) {
+            serde_json::to_writer_pretty(&mut report_file, &self.reports)?;
+        } else {
+            write_text_report(&mut report_file, &self.reports)?;
+        }
+
+        eprintln!("Wrote nif report to {}", report_path.display());
+
+        Ok(self.reports)
+    }
+}
+
+fn write_text_report<W: Write>(out: &mut W, reports: &[FileReport]) -> anyhow::Result<()> {
+    for report in reports.iter() {
+        writeln!(
+            out,
+            "[{}] {} of {} nifs parsed",
+            report.file, report.parsed, report.total
+        )?;
+
+        for (kind, failures) in report.failures.iter() {
+            writeln!(out, "  {} ({})", kind, failures.len())?;
+            for failure in failures.iter() {
+                write!(
+                    out,
+                    "    {} at {}+{}: {}",
+                    failure.id, failure.offset, failure.length, failure.error
+                )?;
+                if let Some(dump_path) = &failure.dump_path {
+                    write!(out, " -> {}", dump_path)?;
+                }
+                writeln!(out)?;
+            }
+        }
+    }
+
+    Ok(())
+}
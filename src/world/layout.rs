@@ -0,0 +1,184 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::Clap;
+use serde::Deserialize;
+
+use crate::error::{parse_file, read_json};
+
+/// Name of the optional descriptor in a world directory.
+const DESCRIPTOR_FILE_NAME: &str = "world.json";
+
+#[derive(Clap)]
+pub struct LayoutOpts {
+    #[clap(short, long, about = "input directory")]
+    input_path: String,
+    #[clap(
+        long,
+        about = "area index used in the default file names, e.g. 1 for terrain1.lf"
+    )]
+    area: Option<u32>,
+    #[clap(long, about = "terrain file, relative to the input directory")]
+    lf_path: Option<String>,
+    #[clap(long, about = "block object file, relative to the input directory")]
+    lbf_path: Option<String>,
+    #[clap(long, about = "model table file, relative to the input directory")]
+    lof_path: Option<String>,
+    #[clap(long, about = "object list file, relative to the input directory")]
+    loi_path: Option<String>,
+}
+
+/// Optional `world.json` next to the world files, for installs that don't
+/// follow the usual naming. Every entry is optional.
+#[derive(Default, Deserialize)]
+struct Descriptor {
+    area: Option<u32>,
+    lf: Option<String>,
+    lbf: Option<String>,
+    lof: Option<String>,
+    loi: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+pub enum WorldFile {
+    Lf,
+    Lbf,
+    Lof,
+    Loi,
+}
+
+impl WorldFile {
+    fn default_path(self, area: u32) -> String {
+        match self {
+            WorldFile::Lf => format!("terrain{}.lf", area),
+            WorldFile::Lbf => format!("blockObj{}.lbf", area),
+            WorldFile::Lof => format!("modeltable{}.lof", area),
+            WorldFile::Loi => format!("Main/object{}.loi", area),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            WorldFile::Lf => "lf",
+            WorldFile::Lbf => "lbf",
+            WorldFile::Lof => "lof",
+            WorldFile::Loi => "loi",
+        }
+    }
+}
+
+/// Resolved world directory, file paths are looked up on demand so commands
+/// only need the files they actually read.
+pub struct WorldLayout {
+    root: PathBuf,
+    area: u32,
+    overrides: [Option<String>; 4],
+}
+
+impl LayoutOpts {
+    pub fn resolve(&self) -> anyhow::Result<WorldLayout> {
+        WorldLayout::new(
+            PathBuf::from(&self.input_path),
+            self.area,
+            [
+                self.lf_path.clone(),
+                self.lbf_path.clone(),
+                self.lof_path.clone(),
+                self.loi_path.clone(),
+            ],
+        )
+    }
+}
+
+impl WorldLayout {
+    /// Explicit values take precedence over the descriptor in `root`.
+    pub fn new(
+        root: PathBuf,
+        area: Option<u32>,
+        overrides: [Option<String>; 4],
+    ) -> anyhow::Result<WorldLayout> {
+        let descriptor = match find_case_insensitive(&root, DESCRIPTOR_FILE_NAME) {
+            Some(descriptor_path) => read_json(&descriptor_path)?,
+            None => Descriptor::default(),
+        };
+
+        let [lf, lbf, lof, loi] = overrides;
+
+        Ok(WorldLayout {
+            root,
+            area: area.or(descriptor.area).unwrap_or(0),
+            overrides: [
+                lf.or(descriptor.lf),
+                lbf.or(descriptor.lbf),
+                lof.or(descriptor.lof),
+                loi.or(descriptor.loi),
+            ],
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn path(&self, file: WorldFile) -> anyhow::Result<PathBuf> {
+        let relative = match &self.overrides[file as usize] {
+            Some(relative) => relative.clone(),
+            None => file.default_path(self.area),
+        };
+
+        find_case_insensitive(&self.root, &relative).with_context(|| {
+            format!(
+                "could not find {} file {:?} in {}",
+                file.name(),
+                relative,
+                self.root.display()
+            )
+        })
+    }
+
+    /// Finds and parses one of the world files, see `crate::error::parse_file`.
+    pub fn parse<T, F>(&self, file: WorldFile, parse: F) -> anyhow::Result<(T, File)>
+    where
+        F: FnOnce(&mut File) -> anyhow::Result<T>,
+    {
+        Ok(parse_file(&self.path(file)?, parse)?)
+    }
+}
+
+/// Looks up a relative path one component at a time, falling back to a case
+/// insensitive match in each directory. Both `/` and `\` separate components.
+pub fn find_case_insensitive(base: &Path, relative: &str) -> Option<PathBuf> {
+    let relative_path = Path::new(relative);
+    if relative_path.is_absolute() {
+        return relative_path.exists().then(|| relative_path.to_path_buf());
+    }
+
+    let mut path = base.to_path_buf();
+
+    for component in relative.split(['/', '\\']) {
+        if component.is_empty() || component == "." {
+            continue;
+        }
+
+        let exact = path.join(component);
+        if exact.exists() {
+            path = exact;
+            continue;
+        }
+
+        let entry = std::fs::read_dir(&path).ok()?.find_map(|entry| {
+            let entry = entry.ok()?;
+            entry
+                .file_name()
+                .to_str()?
+                .eq_ignore_ascii_case(component)
+                .then(|| entry.path())
+        })?;
+        path = entry;
+    }
+
+    Some(path)
+}
@@ -0,0 +1,312 @@
+use std::{fs::File, io::Write, path::Path};
+
+use clap::Clap;
+use serde::Serialize;
+
+use super::layout::{LayoutOpts, WorldFile};
+use crate::error::{Error, PathContext};
+
+/// Far beyond any shipped terrain, only there to catch corrupt headers
+/// before allocating the grid.
+const MAX_GRID_BLOCKS: u32 = 1 << 20;
+
+/// Largest png, in pixels, that `--scale` may ask for. 64 megapixels is
+/// about 200MB of image data.
+const MAX_IMAGE_PIXELS: u64 = 1 << 26;
+
+#[derive(Clap)]
+pub struct MapOpts {
+    #[clap(flatten)]
+    layout: LayoutOpts,
+    #[clap(
+        short,
+        long,
+        about = "write the map to a .png, .svg or .json file instead of printing it"
+    )]
+    output_path: Option<String>,
+    #[clap(long, about = "shade blocks that have terrain in the lf file")]
+    lf_overlay: bool,
+    #[clap(long, about = "add lbf block object counts to the blue channel")]
+    lbf_overlay: bool,
+    #[clap(
+        long,
+        default_value = "4",
+        about = "pixels per block in png and svg output"
+    )]
+    scale: u32,
+}
+
+/// Per-block counts laid out row by row, `rows[y][x]` is block `y * size_x + x`.
+#[derive(Serialize)]
+struct DensityMap {
+    size_x: u32,
+    size_y: u32,
+    loi_objects: Vec<Vec<u32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lf_terrain: Option<Vec<Vec<bool>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lbf_objects: Option<Vec<Vec<u32>>>,
+}
+
+fn to_rows<T: Clone + Default>(values: &[T], size_x: u32, size_y: u32) -> Vec<Vec<T>> {
+    (0..size_y)
+        .map(|y| {
+            (0..size_x)
+                .map(|x| {
+                    values
+                        .get((y * size_x + x) as usize)
+                        .cloned()
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+impl DensityMap {
+    fn max(rows: &[Vec<u32>]) -> u32 {
+        rows.iter().flatten().copied().max().unwrap_or(0).max(1)
+    }
+
+    /// Red to yellow by object count, grey background for terrain and blue
+    /// for block objects when those overlays are enabled.
+    fn color(&self, x: usize, y: usize, loi_max: u32, lbf_max: u32) -> [u8; 3] {
+        let mut color = [0u8; 3];
+
+        if let Some(lf_terrain) = &self.lf_terrain {
+            if lf_terrain[y][x] {
+                color = [48, 48, 48];
+            }
+        }
+
+        let count = self.loi_objects[y][x];
+        if count > 0 {
+            let t = (count as f32 / loi_max as f32).sqrt();
+            color[0] = 255;
+            color[1] = (t * 255.0) as u8;
+        }
+
+        if let Some(lbf_objects) = &self.lbf_objects {
+            let count = lbf_objects[y][x];
+            if count > 0 {
+                let t = (count as f32 / lbf_max as f32).sqrt();
+                color[2] = 64 + (t * 191.0) as u8;
+            }
+        }
+
+        color
+    }
+
+    fn maxima(&self) -> (u32, u32) {
+        (
+            Self::max(&self.loi_objects),
+            self.lbf_objects.as_deref().map_or(1, Self::max),
+        )
+    }
+
+    /// Image width and height at `scale` pixels per block.
+    fn image_size(&self, scale: u32) -> anyhow::Result<(u32, u32)> {
+        match (
+            self.size_x.checked_mul(scale),
+            self.size_y.checked_mul(scale),
+        ) {
+            (Some(width), Some(height)) => Ok((width, height)),
+            _ => anyhow::bail!(
+                "--scale {} is too large for a {}x{} grid",
+                scale,
+                self.size_x,
+                self.size_y
+            ),
+        }
+    }
+
+    fn write_png<W: Write>(&self, out: W, scale: u32) -> anyhow::Result<()> {
+        let (loi_max, lbf_max) = self.maxima();
+        let (width, height) = self.image_size(scale)?;
+        anyhow::ensure!(
+            width as u64 * height as u64 <= MAX_IMAGE_PIXELS,
+            "a {}x{} png is too large, lower --scale",
+            width,
+            height
+        );
+
+        let mut data = Vec::with_capacity(width as usize * height as usize * 3);
+        for py in 0..height {
+            for px in 0..width {
+                let color = self.color(
+                    (px / scale) as usize,
+                    (py / scale) as usize,
+                    loi_max,
+                    lbf_max,
+                );
+                data.extend_from_slice(&color);
+            }
+        }
+
+        let mut encoder = png::Encoder::new(out, width, height);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&data)?;
+
+        Ok(())
+    }
+
+    fn write_svg<W: Write>(&self, out: &mut W, scale: u32) -> anyhow::Result<()> {
+        let (loi_max, lbf_max) = self.maxima();
+        let (width, height) = self.image_size(scale)?;
+
+        writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" shape-rendering="crispEdges">"#,
+            width, height
+        )?;
+        writeln!(out, r#"<rect width="100%" height="100%" fill="black"/>"#)?;
+
+        for y in 0..self.size_y as usize {
+            for x in 0..self.size_x as usize {
+                let [r, g, b] = self.color(x, y, loi_max, lbf_max);
+                if [r, g, b] == [0, 0, 0] {
+                    continue;
+                }
+
+                write!(
+                    out,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="rgb({},{},{})">"#,
+                    x as u32 * scale,
+                    y as u32 * scale,
+                    scale,
+                    scale,
+                    r,
+                    g,
+                    b
+                )?;
+                write!(
+                    out,
+                    "<title>block {}: {} objects",
+                    y * self.size_x as usize + x,
+                    self.loi_objects[y][x]
+                )?;
+                if let Some(lbf_objects) = &self.lbf_objects {
+                    write!(out, ", {} block objects", lbf_objects[y][x])?;
+                }
+                writeln!(out, "</title></rect>")?;
+            }
+        }
+
+        writeln!(out, "</svg>")?;
+
+        Ok(())
+    }
+
+    fn print_ascii(&self) {
+        println!("Object count by block:");
+        print!("    ");
+        for x in 0..self.size_x {
+            match x % 10 {
+                0 => print!("{}", x / 10),
+                _ => print!(" "),
+            }
+        }
+        println!();
+        print!("    ");
+        for x in 0..self.size_x {
+            print!("{}", x % 10);
+        }
+        println!();
+        for (y, row_counts) in self.loi_objects.iter().enumerate() {
+            print!("{:03} ", y);
+            for object_count in row_counts.iter() {
+                match object_count {
+                    0 => print!("_"),
+                    1..=9 => print!("{}", object_count),
+                    _ => print!("+"),
+                };
+            }
+            println!();
+        }
+    }
+}
+
+pub fn process_map(map_opts: MapOpts) -> anyhow::Result<()> {
+    anyhow::ensure!(map_opts.scale > 0, "--scale has to be at least 1");
+
+    let layout = map_opts.layout.resolve()?;
+
+    let (lf, _) = layout.parse(WorldFile::Lf, slidetown::parsers::lf::Lf::parse)?;
+    let (loi, _) = layout.parse(WorldFile::Loi, slidetown::parsers::loi::Loi::parse)?;
+
+    let size_x = lf.header.size_x;
+    let size_y = lf.header.size_y;
+    let grid_blocks = size_x.checked_mul(size_y);
+    if grid_blocks
+        .filter(|&size| size <= MAX_GRID_BLOCKS)
+        .is_none()
+    {
+        return Err(Error::Parse {
+            path: Some(layout.path(WorldFile::Lf)?),
+            offset: None,
+            error: anyhow::anyhow!("terrain grid {}x{} is too large", size_x, size_y),
+        }
+        .into());
+    }
+
+    let loi_counts: Vec<u32> = loi.blocks.iter().map(|b| b.object_count).collect();
+
+    let lf_terrain = if map_opts.lf_overlay {
+        let mut terrain = vec![false; (size_x * size_y) as usize];
+        for block in lf.blocks.iter().filter(|b| b.file_length > 0) {
+            if let Some(present) = terrain.get_mut(block.index as usize) {
+                *present = true;
+            }
+        }
+        Some(to_rows(&terrain, size_x, size_y))
+    } else {
+        None
+    };
+
+    let lbf_objects = if map_opts.lbf_overlay {
+        let (lbf, _) = layout.parse(WorldFile::Lbf, slidetown::parsers::lbf::Lbf::parse)?;
+        let lbf_counts: Vec<u32> = lbf.blocks.iter().map(|b| b.object_count).collect();
+        Some(to_rows(&lbf_counts, size_x, size_y))
+    } else {
+        None
+    };
+
+    let map = DensityMap {
+        size_x,
+        size_y,
+        loi_objects: to_rows(&loi_counts, size_x, size_y),
+        lf_terrain,
+        lbf_objects,
+    };
+
+    let output_path = match &map_opts.output_path {
+        Some(output_path) => Path::new(output_path),
+        None => {
+            map.print_ascii();
+            return Ok(());
+        }
+    };
+
+    let extension = output_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+    anyhow::ensure!(
+        matches!(extension.as_str(), "png" | "svg" | "json"),
+        "unsupported map format {}, expected .png, .svg or .json",
+        output_path.display()
+    );
+
+    let mut file = File::create(output_path).with_path(output_path)?;
+    match extension.as_str() {
+        "png" => map.write_png(&mut file, map_opts.scale)?,
+        "svg" => map.write_svg(&mut file, map_opts.scale)?,
+        _ => serde_json::to_writer_pretty(&mut file, &map)?,
+    }
+
+    eprintln!("Wrote map to {}", output_path.display());
+
+    Ok(())
+}
@@ -0,0 +1,245 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::Clap;
+use serde::{Deserialize, Serialize};
+use slidetown::parsers::{lbf, lf};
+
+use super::layout::{LayoutOpts, WorldFile};
+use crate::error::{read_json, Error, PathContext};
+
+/// Name of the project manifest at the top of an unpacked world.
+const PROJECT_FILE_NAME: &str = "project.json";
+
+#[derive(Clap)]
+pub struct UnpackOpts {
+    #[clap(flatten)]
+    layout: LayoutOpts,
+    #[clap(short, long, about = "output project directory")]
+    output_path: String,
+}
+
+#[derive(Clap)]
+pub struct PackOpts {
+    #[clap(short, long, about = "input project directory")]
+    input_path: String,
+    #[clap(short, long, about = "output world directory")]
+    output_path: String,
+    #[clap(long, about = "pack even if the files do not agree with each other")]
+    force: bool,
+}
+
+/// Where one world file went inside the project.
+#[derive(Serialize, Deserialize)]
+struct ProjectEntry {
+    /// Path of the packed file relative to the world directory
+    file_name: String,
+    /// Unpacked form relative to the project directory, a manifest
+    path: String,
+}
+
+/// Top-level manifest tying the unpacked world files together.
+#[derive(Serialize, Deserialize)]
+struct Project {
+    lf: ProjectEntry,
+    lbf: ProjectEntry,
+    lof: ProjectEntry,
+    loi: ProjectEntry,
+}
+
+/// Only plain relative paths can be placed back below the output directory.
+fn is_contained(path: &Path) -> bool {
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+/// `path` relative to the world directory with `/` separators.
+fn relative_string(path: &Path, root: &Path) -> anyhow::Result<String> {
+    let relative = path
+        .strip_prefix(root)
+        .ok()
+        .filter(|relative| is_contained(relative))
+        .with_context(|| {
+            format!(
+                "{} is outside of the world directory {}",
+                path.display(),
+                root.display()
+            )
+        })?;
+
+    Ok(relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+pub fn process_unpack(unpack_opts: UnpackOpts) -> anyhow::Result<()> {
+    let layout = unpack_opts.layout.resolve()?;
+    let out_dir_path = Path::new(&unpack_opts.output_path);
+    std::fs::create_dir_all(out_dir_path)?;
+
+    let lf_path = layout.path(WorldFile::Lf)?;
+    let lbf_path = layout.path(WorldFile::Lbf)?;
+    let lof_path = layout.path(WorldFile::Lof)?;
+    let loi_path = layout.path(WorldFile::Loi)?;
+
+    let project = Project {
+        lf: ProjectEntry {
+            file_name: relative_string(&lf_path, layout.root())?,
+            path: "terrain/manifest.json".to_string(),
+        },
+        lbf: ProjectEntry {
+            file_name: relative_string(&lbf_path, layout.root())?,
+            path: "blockobjects/manifest.json".to_string(),
+        },
+        lof: ProjectEntry {
+            file_name: relative_string(&lof_path, layout.root())?,
+            path: "models/manifest.json".to_string(),
+        },
+        loi: ProjectEntry {
+            file_name: relative_string(&loi_path, layout.root())?,
+            path: "objects.json".to_string(),
+        },
+    };
+
+    eprintln!("[lf] Unpacking {}", lf_path.display());
+    crate::lf::unpack_file(&lf_path, &out_dir_path.join("terrain"))?;
+
+    eprintln!("[lbf] Unpacking {}", lbf_path.display());
+    crate::lbf::unpack_file(&lbf_path, &out_dir_path.join("blockobjects"))?;
+
+    eprintln!("[lof] Unpacking {}", lof_path.display());
+    crate::lof::unpack_file(&lof_path, &out_dir_path.join("models"))?;
+
+    eprintln!("[loi] Unpacking {}", loi_path.display());
+    let loi = crate::loi::read_loi(&loi_path)?;
+    crate::loi::write_loi_file(&out_dir_path.join(&project.loi.path), &loi)?;
+
+    let project_path = out_dir_path.join(PROJECT_FILE_NAME);
+    let project_file = File::create(&project_path).with_path(&project_path)?;
+    serde_json::to_writer_pretty(project_file, &project)?;
+
+    Ok(())
+}
+
+/// Cross-file references that the per-format packers can't see.
+fn check_consistency(
+    lf: &lf::Lf,
+    lbf: &lbf::Lbf,
+    model_indices: &HashSet<u32>,
+    loi: &slidetown::parsers::loi::Loi,
+) -> Vec<String> {
+    let mut issues = Vec::new();
+    let grid_size = lf.header.size_x.saturating_mul(lf.header.size_y);
+
+    if lf.header.block_count as usize != lf.blocks.len() {
+        issues.push(format!(
+            "lf block_count is {} but there are {} blocks",
+            lf.header.block_count,
+            lf.blocks.len()
+        ));
+    }
+
+    // lbf blocks have no index of their own, they line up with the terrain
+    // blocks by position
+    for block_position in (grid_size as usize)..lbf.blocks.len() {
+        issues.push(format!(
+            "lbf block {} is outside of the {}x{} terrain grid",
+            block_position, lf.header.size_x, lf.header.size_y
+        ));
+    }
+
+    if loi.blocks.len() > grid_size as usize {
+        issues.push(format!(
+            "loi has {} blocks but the terrain grid only has {}",
+            loi.blocks.len(),
+            grid_size
+        ));
+    }
+
+    for block in loi.blocks.iter() {
+        if block.block_index >= grid_size {
+            issues.push(format!(
+                "loi block {} is outside of the {}x{} terrain grid",
+                block.block_index, lf.header.size_x, lf.header.size_y
+            ));
+        }
+
+        for object in block.objects.iter() {
+            if !model_indices.contains(&object.model_table_index) {
+                issues.push(format!(
+                    "loi object {} uses model {} which is not in the model table",
+                    object.object_index, object.model_table_index
+                ));
+            }
+        }
+    }
+
+    issues
+}
+
+pub fn process_pack(pack_opts: PackOpts) -> anyhow::Result<()> {
+    let project_dir_path = Path::new(&pack_opts.input_path);
+    let out_dir_path = Path::new(&pack_opts.output_path);
+
+    let project: Project = read_json(&project_dir_path.join(PROJECT_FILE_NAME))?;
+
+    let lf_manifest_path = project_dir_path.join(&project.lf.path);
+    let lbf_manifest_path = project_dir_path.join(&project.lbf.path);
+    let lof_manifest_path = project_dir_path.join(&project.lof.path);
+    let loi_path = project_dir_path.join(&project.loi.path);
+
+    let lf: lf::Lf = read_json(&lf_manifest_path)?;
+    let lbf: lbf::Lbf = read_json(&lbf_manifest_path)?;
+    let lof = crate::lof::read_manifest(&lof_manifest_path)?;
+    let loi = crate::loi::read_loi(&loi_path)?;
+
+    let model_indices: HashSet<u32> = lof.models.iter().map(|m| m.index).collect();
+    let issues = check_consistency(&lf, &lbf, &model_indices, &loi);
+    for issue in issues.iter() {
+        eprintln!("{}", issue);
+    }
+    if !issues.is_empty() && !pack_opts.force {
+        let count = issues.len();
+        return Err(Error::Inconsistent(issues)).with_context(|| {
+            format!(
+                "Found {} consistency issues, use --force to pack anyway",
+                count
+            )
+        });
+    }
+
+    let output_file_path = |entry: &ProjectEntry| -> anyhow::Result<PathBuf> {
+        anyhow::ensure!(
+            is_contained(Path::new(&entry.file_name)),
+            "{:?} in {} has to be a relative path inside the output directory",
+            entry.file_name,
+            PROJECT_FILE_NAME
+        );
+
+        let path = out_dir_path.join(&entry.file_name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(path)
+    };
+
+    eprintln!("[lf] Packing {}", project.lf.file_name);
+    crate::lf::pack_file(&lf_manifest_path, &output_file_path(&project.lf)?)?;
+
+    eprintln!("[lbf] Packing {}", project.lbf.file_name);
+    crate::lbf::pack_file(&lbf_manifest_path, &output_file_path(&project.lbf)?)?;
+
+    eprintln!("[lof] Packing {}", project.lof.file_name);
+    crate::lof::pack_file(&lof_manifest_path, &output_file_path(&project.lof)?)?;
+
+    eprintln!("[loi] Packing {}", project.loi.file_name);
+    crate::loi::write_loi_file(&output_file_path(&project.loi)?, &loi)?;
+
+    Ok(())
+}
@@ -0,0 +1,221 @@
+//! Read-only subcommands have to succeed on the synthetic fixtures.
+
+mod common;
+
+use common::{assert_cli_success, cli, TempDir};
+
+fn world_dir(name: &str) -> TempDir {
+    let dir = TempDir::new(name);
+    common::write_world(dir.path());
+    std::fs::write(dir.join("levelmodifier.bin"), common::levelmodifier()).unwrap();
+    std::fs::write(dir.join("data.agt"), common::agt()).unwrap();
+    dir
+}
+
+#[test]
+fn info_commands() {
+    let dir = world_dir("info");
+
+    for (archive, file) in [
+        ("agt", "data.agt"),
+        ("lf", "terrain0.lf"),
+        ("lbf", "blockObj0.lbf"),
+        ("lof", "modeltable0.lof"),
+        ("loi", "Main/object0.loi"),
+        ("levelmodifier", "levelmodifier.bin"),
+        ("world", "."),
+    ] {
+        assert_cli_success(&[archive, "info", "-i", &dir.arg(file)]);
+    }
+}
+
+/// stdout has to hold nothing but the JSON object for piping.
+#[test]
+fn info_commands_json() {
+    let dir = world_dir("info-json");
+
+    for (archive, file, pointer, expected) in [
+        (
+            "agt",
+            "data.agt",
+            "/files/1/decompressed_length",
+            0x8000 + 100,
+        ),
+        ("lf", "terrain0.lf", "/size_x", 2),
+        ("lbf", "blockObj0.lbf", "/block_object_count", 1),
+        ("lof", "modeltable0.lof", "/model_count", 2),
+        ("loi", "Main/object0.loi", "/object_count", 2),
+        ("levelmodifier", "levelmodifier.bin", "/groups/0/ids/1", 1202),
+        ("world", ".", "/loi/object_count", 2),
+    ] {
+        let output =
+            assert_cli_success(&["--format", "json", archive, "info", "-i", &dir.arg(file)]);
+        let info: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(
+            info.pointer(pointer),
+            Some(&expected.into()),
+            "{} info",
+            archive
+        );
+    }
+}
+
+#[test]
+fn levelmodifier_info_by_id() {
+    let dir = world_dir("levelmodifier-info");
+
+    let output = assert_cli_success(&[
+        "levelmodifier",
+        "info",
+        "-i",
+        &dir.arg("levelmodifier.bin"),
+        "--id",
+        "1202",
+    ]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("110"));
+}
+
+#[test]
+fn levelmodifier_diff_and_apply() {
+    let dir = world_dir("levelmodifier-apply");
+
+    assert_cli_success(&[
+        "levelmodifier",
+        "apply",
+        "-i",
+        &dir.arg("levelmodifier.bin"),
+        "-o",
+        &dir.arg("buffed.json"),
+        "-e",
+        "speed[1201][0-2] *= 2",
+    ]);
+
+    assert_cli_success(&[
+        "levelmodifier",
+        "diff",
+        &dir.arg("levelmodifier.bin"),
+        &dir.arg("buffed.json"),
+        "--report-format",
+        "json",
+        "-o",
+        &dir.arg("report.json"),
+    ]);
+    let report: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(dir.join("report.json")).unwrap()).unwrap();
+    let speed = &report["groups"][0];
+    assert_eq!(speed["name"], "speed");
+    let changes = speed["changes"].as_array().unwrap();
+    assert_eq!(changes.len(), 3);
+    assert_eq!(changes[2]["id"], 1201);
+    assert_eq!(changes[2]["level"], 2);
+    assert_eq!(changes[2]["new"], 202.0);
+}
+
+#[test]
+fn world_diff_of_identical_worlds() {
+    let dir = TempDir::new("world-diff");
+    common::write_world(&dir.join("old"));
+    common::write_world(&dir.join("new"));
+
+    let output = assert_cli_success(&["world", "diff", &dir.arg("old"), &dir.arg("new")]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for file in ["lf", "lbf", "lof", "loi"] {
+        assert!(
+            stdout.contains(&format!("[{}] no changes", file)),
+            "{}",
+            stdout
+        );
+    }
+}
+
+#[test]
+fn world_diff_reports_removed_object() {
+    let dir = TempDir::new("world-diff-removed");
+    common::write_world(&dir.join("old"));
+    common::write_world(&dir.join("new"));
+
+    let new_loi = dir.arg("new/Main/object0.loi");
+    assert_cli_success(&[
+        "loi",
+        "remove-object",
+        "-i",
+        &new_loi,
+        "-o",
+        &new_loi,
+        "--index",
+        "1",
+    ]);
+
+    let output = assert_cli_success(&[
+        "world",
+        "diff",
+        &dir.arg("old"),
+        &dir.arg("new"),
+        "-o",
+        &dir.arg("changes.json"),
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("[lf] no changes"), "{}", stdout);
+    assert!(stdout.contains("[loi] 1 loi_object_removed"), "{}", stdout);
+
+    let changes: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(dir.join("changes.json")).unwrap()).unwrap();
+    assert_eq!(changes.as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn loi_rebucket_single_row_map() {
+    let dir = world_dir("loi-rebucket");
+
+    // the 2x1 terrain only gives a block size along x
+    let output = assert_cli_success(&[
+        "loi",
+        "rebucket",
+        "-i",
+        &dir.arg("Main/object0.loi"),
+        "--lf",
+        &dir.arg("terrain0.lf"),
+        "-o",
+        &dir.arg("rebucketed.loi"),
+    ]);
+    assert_eq!(
+        std::fs::read(dir.join("rebucketed.loi")).unwrap(),
+        common::loi(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn missing_input_fails() {
+    let dir = TempDir::new("missing");
+
+    let output = cli(&["lf", "info", "-i", &dir.arg("nope.lf")]);
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn malformed_input_fails() {
+    let dir = TempDir::new("malformed");
+    std::fs::write(dir.join("garbage.lf"), b"not a terrain file").unwrap();
+
+    let mut truncated = common::lf();
+    truncated.truncate(truncated.len() - 4);
+    std::fs::write(dir.join("truncated.lf"), truncated).unwrap();
+
+    let output = cli(&["lf", "info", "-i", &dir.arg("garbage.lf")]);
+    assert_eq!(output.status.code(), Some(4));
+
+    // The table is intact, so this only fails once the last nif is read.
+    let output = cli(&[
+        "lf",
+        "unpack",
+        "-i",
+        &dir.arg("truncated.lf"),
+        "-o",
+        &dir.arg("unpacked"),
+    ]);
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("block 1"), "{}", stderr);
+}
@@ -0,0 +1,381 @@
+//! Synthetic world files for the integration tests. Game files can't be
+//! committed, so every fixture is built in code with the library encoders.
+
+#![allow(dead_code)]
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use slidetown::parsers::{agt, lbf, levelmodifier, lf, lof, loi};
+use slidetown_cli::{
+    agt::{AgtWriter, CHUNK_SIZE, SPOOKY_KEY},
+    Encode,
+};
+
+/// Version date the LF packer always writes.
+pub const VERSION_DATE: u32 = 20090406;
+
+/// Start of a Gamebryo nif header. The fixtures only need unpack and pack to
+/// move the payload around, so the rest of the file is just a tag.
+const NIF_HEADER: &[u8] = b"Gamebryo File Format, Version 20.0.0.4\n";
+
+pub fn nif_payload(tag: &str) -> Vec<u8> {
+    let mut payload = NIF_HEADER.to_vec();
+    payload.extend_from_slice(&0x1400_0004_u32.to_le_bytes());
+    payload.extend_from_slice(tag.as_bytes());
+    payload
+}
+
+/// Scratch directory under the system temp dir, removed on drop.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "slidetown-cli-test-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.0.join(path)
+    }
+
+    /// `join` as a command line argument.
+    pub fn arg<P: AsRef<Path>>(&self, path: P) -> String {
+        self.join(path).to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+pub fn cli(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_slidetown-cli"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Runs the binary and fails the test with its output if it exits non-zero.
+pub fn assert_cli_success(args: &[&str]) -> Output {
+    let output = cli(args);
+    assert!(
+        output.status.success(),
+        "{:?} failed\nstdout:\n{}\nstderr:\n{}",
+        args,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+fn with_payloads<T: Encode>(table: &T, payloads: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    table.encode(&mut bytes).unwrap();
+    for payload in payloads.iter() {
+        bytes.extend_from_slice(payload);
+    }
+    bytes
+}
+
+/// 2x1 terrain with one nif per block, blocks are 100 units wide.
+pub fn lf() -> Vec<u8> {
+    let payloads: Vec<Vec<u8>> = (0..2)
+        .map(|index| nif_payload(&format!("terrain block {}", index)))
+        .collect();
+
+    let mut lf = lf::Lf {
+        header: lf::Header {
+            unknown1: 1,
+            version_date: VERSION_DATE,
+            unknown2: 0,
+            block_count: 2,
+            unknown3: vec![0; 13],
+            size_x: 2,
+            size_y: 1,
+            size_idx: 1,
+            unknown4: vec![0.0; 5],
+        },
+        blocks: payloads
+            .iter()
+            .enumerate()
+            .map(|(index, payload)| lf::Block {
+                index: index as u32,
+                position_x: index as u32 * 100,
+                position_y: 0,
+                file_offset: 0,
+                file_length: payload.len() as u32,
+                unknown: 0,
+            })
+            .collect(),
+    };
+
+    let mut file_offset = lf.encoded_len().unwrap() as u32;
+    for block in lf.blocks.iter_mut() {
+        block.file_offset = file_offset;
+        file_offset += block.file_length;
+    }
+
+    with_payloads(&lf, &payloads)
+}
+
+/// One object in the first terrain block.
+pub fn lbf() -> Vec<u8> {
+    let payloads = vec![nif_payload("block object 0")];
+
+    let mut lbf = lbf::Lbf {
+        header: lbf::Header {
+            unknown1: 1,
+            version_date: VERSION_DATE,
+            unknown2: 0,
+            block_count: 1,
+            block_object_count: 1,
+        },
+        blocks: vec![lbf::Block {
+            object_count: 1,
+            objects: vec![lbf::BlockObject {
+                unk: 0,
+                index: 0,
+                file_offset: 0,
+                file_length: payloads[0].len() as u32,
+            }],
+        }],
+    };
+
+    lbf.blocks[0].objects[0].file_offset = lbf.encoded_len().unwrap() as u32;
+
+    with_payloads(&lbf, &payloads)
+}
+
+/// Two models with game style backslash paths, one with a Korean name.
+pub fn lof() -> Vec<u8> {
+    let models = [("tree", "model\\tree.nif"), ("바위", "model\\rock.nif")];
+    let payloads: Vec<Vec<u8>> = models
+        .iter()
+        .map(|(_, file_name)| nif_payload(file_name))
+        .collect();
+
+    let mut lof = lof::Lof {
+        header: lof::Header {
+            unknown1: 1,
+            version_date: VERSION_DATE,
+            model_count: models.len() as u32,
+            unknown2: 0,
+        },
+        models: models
+            .iter()
+            .zip(payloads.iter())
+            .enumerate()
+            .map(|(index, ((name, file_name), payload))| lof::Model {
+                index: index as u32,
+                unknown1: 0,
+                unknown2: 0,
+                unknown3: 0,
+                unknown4: 0,
+                unknown5: 0,
+                name: name.to_string(),
+                file_name: file_name.to_string(),
+                unknown6: 1.0,
+                unknown7: 0,
+                unknown8: 0,
+                file_offset: 0,
+                file_length: payload.len() as u32,
+            })
+            .collect(),
+    };
+
+    let mut file_offset = lof.encoded_len().unwrap() as u32;
+    for model in lof.models.iter_mut() {
+        model.file_offset = file_offset;
+        file_offset += model.file_length;
+    }
+
+    with_payloads(&lof, &payloads)
+}
+
+/// One object per terrain block, placing the two models from `lof`.
+pub fn loi() -> Vec<u8> {
+    let identity = ((1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0));
+
+    let loi = loi::Loi {
+        header: loi::Header {
+            unknown1: 1,
+            version_date: VERSION_DATE,
+            block_count: 2,
+        },
+        blocks: (0..2)
+            .map(|index| loi::Block {
+                block_index: index,
+                object_count: 1,
+                objects: vec![loi::BlockObject {
+                    unknown1: 0,
+                    unknown2: 0,
+                    unknown3: 0.0,
+                    unknown4: 0.0,
+                    object_index: index,
+                    block_index: index,
+                    model_table_index: index,
+                    position: (index as f32 * 100.0 + 50.0, 50.0, 0.0),
+                    rotation: identity,
+                    scale: 1.0,
+                    unknown8: 0,
+                    unknown9: 0,
+                    object_extra_index: -1,
+                    unknown11: 0,
+                }],
+            })
+            .collect(),
+        object_extra_count: 0,
+        object_extras: Vec::new(),
+        unknown_objects_2: (0..2)
+            .map(|_| loi::UnknownObject2 {
+                unknown_count: 0,
+                items: Vec::new(),
+            })
+            .collect(),
+        unknown_object_3_count: 0,
+        unknown_objects_3: Vec::new(),
+        unknown_objects_4: (0..2)
+            .map(|_| loi::UnknownObject4 {
+                unknown_count: 0,
+                unknown1: 0,
+                items: Vec::new(),
+            })
+            .collect(),
+        unknown_objects_5: (0..2)
+            .map(|_| loi::UnknownObject5 {
+                object_count: 0,
+                object_indices: Vec::new(),
+            })
+            .collect(),
+    };
+
+    with_payloads(&loi, &[])
+}
+
+/// Current format with 1001 levels. Id column `c` starts at `base + c * 10`
+/// and grows by half a point per level.
+pub fn levelmodifier() -> Vec<u8> {
+    let levels = |base: f32, id_count: usize| -> Vec<levelmodifier::GroupOption> {
+        (0..1001)
+            .map(|level| levelmodifier::GroupOption {
+                values: (0..id_count)
+                    .map(|column| base + column as f32 * 10.0 + level as f32 * 0.5)
+                    .collect(),
+            })
+            .collect()
+    };
+
+    let levelmodifier = levelmodifier::LevelModifier {
+        header: levelmodifier::Header {
+            version_date: VERSION_DATE,
+            speed_length: 2,
+            accel_length: 1,
+            dura_length: 1,
+            boost_length: 1,
+            speed_ids: vec![1201, 1202],
+            accel_ids: vec![1301],
+            dura_ids: vec![1401],
+            boost_ids: vec![1501],
+        },
+        speed: levels(100.0, 2),
+        accel: levels(1.0, 1),
+        dura: levels(50.0, 1),
+        boost: levels(2.0, 1),
+    };
+
+    with_payloads(&levelmodifier, &[])
+}
+
+/// Archive with a small file and one spanning two chunks, laid out the way
+/// `agt pack` writes it.
+pub fn agt() -> Vec<u8> {
+    let files: Vec<(&str, Vec<u8>)> = vec![
+        (
+            "data\\ui\\text.xml",
+            b"<text id=\"1\">hello</text>".to_vec(),
+        ),
+        (
+            "data\\table.bin",
+            (0..CHUNK_SIZE + 100).map(|i| (i % 251) as u8).collect(),
+        ),
+    ];
+
+    let file_chunks: Vec<Vec<Vec<u8>>> = files
+        .iter()
+        .map(|(_, data)| {
+            data.chunks(CHUNK_SIZE)
+                .map(|chunk| miniz_oxide::deflate::compress_to_vec_zlib(chunk, 6))
+                .collect()
+        })
+        .collect();
+
+    let mut entries: Vec<agt::Entry> = files
+        .iter()
+        .zip(file_chunks.iter())
+        .map(|((path, data), chunks)| agt::Entry {
+            header_offset: 0,
+            chunk_count: chunks.len() as u32,
+            decompressed_length: data.len() as u32,
+            path: path.to_string(),
+        })
+        .collect();
+
+    let mut header_offset = 32 + entries.encoded_len().unwrap() as u32;
+    for (entry, chunks) in entries.iter_mut().zip(file_chunks.iter()) {
+        entry.header_offset = header_offset;
+        header_offset += chunks
+            .iter()
+            .map(|chunk| 2 + chunk.len() as u32)
+            .sum::<u32>();
+    }
+
+    let header = agt::Header {
+        what: 1,
+        version: (1, 0),
+        file_count: entries.len() as u32,
+        what2: 0,
+        what3: 0,
+        what4: 0,
+    };
+
+    let mut bytes = Vec::new();
+    header.encode(&mut bytes).unwrap();
+    {
+        let mut writer = AgtWriter::new(&mut bytes, 32, SPOOKY_KEY);
+        entries.encode(&mut writer).unwrap();
+        for chunks in file_chunks.iter() {
+            for chunk in chunks.iter() {
+                (chunk.len() as u16).encode(&mut writer).unwrap();
+            }
+            for chunk in chunks.iter() {
+                writer.write_all(chunk).unwrap();
+            }
+        }
+    }
+    bytes
+}
+
+/// Writes all four world files under their default area 0 names.
+pub fn write_world(dir: &Path) {
+    std::fs::create_dir_all(dir.join("Main")).unwrap();
+    std::fs::write(dir.join("terrain0.lf"), lf()).unwrap();
+    std::fs::write(dir.join("blockObj0.lbf"), lbf()).unwrap();
+    std::fs::write(dir.join("modeltable0.lof"), lof()).unwrap();
+    std::fs::write(dir.join("Main/object0.loi"), loi()).unwrap();
+}
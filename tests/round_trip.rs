@@ -0,0 +1,240 @@
+//! unpack -> pack through the binary has to reproduce the fixture byte for
+//! byte.
+
+mod common;
+
+use std::path::Path;
+
+use common::{assert_cli_success, TempDir};
+
+fn assert_same_bytes(path: &Path, expected: &[u8]) {
+    let actual = std::fs::read(path).unwrap();
+    assert!(
+        actual == expected,
+        "{} differs from the fixture ({} bytes, expected {})",
+        path.display(),
+        actual.len(),
+        expected.len()
+    );
+}
+
+#[test]
+fn lf_unpack_pack() {
+    let dir = TempDir::new("lf");
+    std::fs::write(dir.join("terrain0.lf"), common::lf()).unwrap();
+
+    assert_cli_success(&[
+        "lf",
+        "unpack",
+        "-i",
+        &dir.arg("terrain0.lf"),
+        "-o",
+        &dir.arg("unpacked"),
+    ]);
+    assert!(dir.join("unpacked/0.nif").exists());
+    assert!(dir.join("unpacked/1.nif").exists());
+
+    assert_cli_success(&[
+        "lf",
+        "pack",
+        "-i",
+        &dir.arg("unpacked/manifest.json"),
+        "-o",
+        &dir.arg("repacked.lf"),
+    ]);
+    assert_same_bytes(&dir.join("repacked.lf"), &common::lf());
+}
+
+#[test]
+fn lbf_unpack_pack() {
+    let dir = TempDir::new("lbf");
+    std::fs::write(dir.join("blockObj0.lbf"), common::lbf()).unwrap();
+
+    assert_cli_success(&[
+        "lbf",
+        "unpack",
+        "-i",
+        &dir.arg("blockObj0.lbf"),
+        "-o",
+        &dir.arg("unpacked"),
+    ]);
+    assert!(dir.join("unpacked/0_0.nif").exists());
+
+    assert_cli_success(&[
+        "lbf",
+        "pack",
+        "-i",
+        &dir.arg("unpacked/manifest.json"),
+        "-o",
+        &dir.arg("repacked.lbf"),
+    ]);
+    assert_same_bytes(&dir.join("repacked.lbf"), &common::lbf());
+}
+
+#[test]
+fn agt_unpack_pack() {
+    let dir = TempDir::new("agt");
+    std::fs::write(dir.join("data.agt"), common::agt()).unwrap();
+
+    assert_cli_success(&[
+        "agt",
+        "unpack",
+        "-i",
+        &dir.arg("data.agt"),
+        "-o",
+        &dir.arg("unpacked"),
+    ]);
+    assert_eq!(
+        std::fs::read(dir.join("unpacked/data/ui/text.xml")).unwrap(),
+        b"<text id=\"1\">hello</text>"
+    );
+    assert!(dir.join("unpacked/data/table.bin").exists());
+
+    assert_cli_success(&[
+        "agt",
+        "pack",
+        "-i",
+        &dir.arg("unpacked/manifest.json"),
+        "-o",
+        &dir.arg("repacked.agt"),
+    ]);
+    assert_same_bytes(&dir.join("repacked.agt"), &common::agt());
+}
+
+#[test]
+fn lof_unpack_pack() {
+    let dir = TempDir::new("lof");
+    std::fs::write(dir.join("modeltable0.lof"), common::lof()).unwrap();
+
+    assert_cli_success(&[
+        "lof",
+        "unpack",
+        "-i",
+        &dir.arg("modeltable0.lof"),
+        "-o",
+        &dir.arg("unpacked"),
+    ]);
+    assert!(dir.join("unpacked/model/tree.nif").exists());
+    assert!(dir.join("unpacked/model/rock.nif").exists());
+
+    assert_cli_success(&[
+        "lof",
+        "pack",
+        "-i",
+        &dir.arg("unpacked/manifest.json"),
+        "-o",
+        &dir.arg("repacked.lof"),
+    ]);
+    assert_same_bytes(&dir.join("repacked.lof"), &common::lof());
+}
+
+#[test]
+fn loi_unpack_pack() {
+    let dir = TempDir::new("loi");
+    std::fs::write(dir.join("object0.loi"), common::loi()).unwrap();
+
+    for (manifest, extra_args) in [("plain.json", &[][..]), ("decoded.json", &["--decode"][..])] {
+        let mut unpack_args = vec![
+            "loi".to_string(),
+            "unpack".to_string(),
+            "-i".to_string(),
+            dir.arg("object0.loi"),
+            "-o".to_string(),
+            dir.arg(manifest),
+        ];
+        unpack_args.extend(extra_args.iter().map(|arg| arg.to_string()));
+        assert_cli_success(&unpack_args.iter().map(String::as_str).collect::<Vec<_>>());
+
+        let repacked = format!("{}.loi", manifest);
+        assert_cli_success(&[
+            "loi",
+            "pack",
+            "-i",
+            &dir.arg(manifest),
+            "-o",
+            &dir.arg(&repacked),
+        ]);
+        assert_same_bytes(&dir.join(&repacked), &common::loi());
+    }
+}
+
+#[test]
+fn levelmodifier_unpack_pack() {
+    let dir = TempDir::new("levelmodifier");
+    std::fs::write(dir.join("levelmodifier.bin"), common::levelmodifier()).unwrap();
+
+    assert_cli_success(&[
+        "levelmodifier",
+        "unpack",
+        "-i",
+        &dir.arg("levelmodifier.bin"),
+        "-o",
+        &dir.arg("levelmodifier.json"),
+    ]);
+    assert_cli_success(&[
+        "levelmodifier",
+        "pack",
+        "-i",
+        &dir.arg("levelmodifier.json"),
+        "-o",
+        &dir.arg("repacked.bin"),
+    ]);
+    assert_same_bytes(&dir.join("repacked.bin"), &common::levelmodifier());
+}
+
+#[test]
+fn levelmodifier_csv_round_trip() {
+    let dir = TempDir::new("levelmodifier-csv");
+    std::fs::write(dir.join("levelmodifier.bin"), common::levelmodifier()).unwrap();
+
+    assert_cli_success(&[
+        "levelmodifier",
+        "export-csv",
+        "-i",
+        &dir.arg("levelmodifier.bin"),
+        "-o",
+        &dir.arg("tables"),
+    ]);
+    assert_cli_success(&[
+        "levelmodifier",
+        "import-csv",
+        "-i",
+        &dir.arg("tables"),
+        "-b",
+        &dir.arg("levelmodifier.bin"),
+        "-o",
+        &dir.arg("repacked.bin"),
+    ]);
+    assert_same_bytes(&dir.join("repacked.bin"), &common::levelmodifier());
+}
+
+/// Covers LBF too, which the world project carries through unchanged.
+#[test]
+fn world_unpack_pack() {
+    let dir = TempDir::new("world");
+    common::write_world(&dir.join("world"));
+
+    assert_cli_success(&[
+        "world",
+        "unpack",
+        "-i",
+        &dir.arg("world"),
+        "-o",
+        &dir.arg("project"),
+    ]);
+    assert!(dir.join("project/project.json").exists());
+
+    assert_cli_success(&[
+        "world",
+        "pack",
+        "-i",
+        &dir.arg("project"),
+        "-o",
+        &dir.arg("repacked"),
+    ]);
+
+    assert_same_bytes(&dir.join("repacked/terrain0.lf"), &common::lf());
+    assert_same_bytes(&dir.join("repacked/blockObj0.lbf"), &common::lbf());
+    assert_same_bytes(&dir.join("repacked/modeltable0.lof"), &common::lof());
+    assert_same_bytes(&dir.join("repacked/Main/object0.loi"), &common::loi());
+}